@@ -0,0 +1,100 @@
+//! How long to wait before trying something again.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// A policy for the delay between repeated attempts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Backoff {
+    /// Always wait the same duration.
+    Fixed(Duration),
+    /// Start with `initial` and double the delay after every attempt, up to `max`.
+    Exponential {
+        /// The delay before the second attempt.
+        initial: Duration,
+        /// The longest delay.
+        max: Duration,
+    },
+    /// Like `Exponential`, but wait a random duration between half the delay and the full delay.
+    ///
+    /// This keeps many threads backing off at the same time from retrying in lockstep.
+    Jittered {
+        /// The delay before the second attempt, before jitter.
+        initial: Duration,
+        /// The longest delay.
+        max: Duration,
+    },
+}
+
+impl Backoff {
+    /// The delay after `attempt` attempts were made already, starting at 0.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => exponential(initial, max, attempt),
+            Backoff::Jittered { initial, max } => {
+                let delay = exponential(initial, max, attempt);
+                delay / 2 + (delay - delay / 2).mul_f64(random_fraction())
+            }
+        }
+    }
+}
+
+fn exponential(initial: Duration, max: Duration, attempt: u32) -> Duration {
+    initial
+        .checked_mul(2u32.saturating_pow(attempt))
+        .map_or(max, |delay| delay.min(max))
+}
+
+/// A random number in `[0, 1)`.
+///
+/// Every `RandomState` is seeded differently, which is random enough for jitter.
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().build_hasher().finish() >> 11;
+    bits as f64 / (1u64 << 53) as f64
+}
+
+impl Default for Backoff {
+    /// Don't wait at all.
+    fn default() -> Backoff {
+        Backoff::Fixed(Duration::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fixed() {
+        let backoff = Backoff::Fixed(Duration::from_millis(10));
+        assert_eq!(Duration::from_millis(10), backoff.delay(0));
+        assert_eq!(Duration::from_millis(10), backoff.delay(100));
+    }
+
+    #[test]
+    fn exponential_is_capped() {
+        let backoff = Backoff::Exponential {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(50),
+        };
+        let delays: Vec<_> = (0..5).map(|i| backoff.delay(i).as_millis()).collect();
+        assert_eq!(vec![10, 20, 40, 50, 50], delays);
+        assert_eq!(Duration::from_millis(50), backoff.delay(u32::MAX));
+    }
+
+    #[test]
+    fn jittered_stays_in_range() {
+        let backoff = Backoff::Jittered {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(50),
+        };
+        for (attempt, full) in [(0, 10), (2, 40), (10, 50)] {
+            let full = Duration::from_millis(full);
+            let delays: Vec<_> = (0..20).map(|_| backoff.delay(attempt)).collect();
+            assert!(delays.iter().all(|d| *d >= full / 2 && *d <= full));
+            assert!(delays.iter().any(|d| *d != delays[0]));
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Asking a thread to stop, then joining it.
+
+use std::sync::Arc;
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use crate::completion::Completion;
+use crate::{spawn, TryJoinError, TryJoinHandle, TryJoinableHandle};
+
+/// A flag a thread checks to know whether it should stop early.
+///
+/// Clones share the same flag. Once cancelled, a token stays cancelled.
+#[derive(Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<Completion>,
+}
+
+impl CancellationToken {
+    /// Create a token that is not cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken {
+            cancelled: Arc::new(Completion::new()),
+        }
+    }
+
+    /// Ask the thread to stop.
+    pub fn cancel(&self) {
+        self.cancelled.complete();
+    }
+
+    /// Whether the thread was asked to stop.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.is_complete()
+    }
+
+    /// Wait for the token to be cancelled, for the specified duration at most.
+    ///
+    /// Returns whether the token is cancelled.
+    /// This is useful as an interruptible `thread::sleep`.
+    pub fn wait_timeout(&self, wait: Duration) -> bool {
+        self.cancelled.wait_until(Instant::now() + wait)
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> CancellationToken {
+        CancellationToken::new()
+    }
+}
+
+/// Spawn a new thread that gets a [`CancellationToken`] to check.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// let t = thread_tryjoin::spawn_cancellable(|token| {
+///     let mut rounds = 0;
+///     while !token.wait_timeout(Duration::from_millis(10)) {
+///         rounds += 1;
+///     }
+///     rounds
+/// });
+///
+/// let result = t.cancel_and_join_timeout(Duration::from_secs(1));
+/// assert!(result.is_ok());
+/// ```
+pub fn spawn_cancellable<F, T>(f: F) -> CancellableHandle<T>
+where
+    F: FnOnce(CancellationToken) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let token = CancellationToken::new();
+    let their_token = token.clone();
+    CancellableHandle {
+        handle: spawn(move || f(their_token)),
+        token,
+    }
+}
+
+/// A [`TryJoinableHandle`] together with the [`CancellationToken`] its thread checks.
+pub struct CancellableHandle<T> {
+    handle: TryJoinableHandle<T>,
+    token: CancellationToken,
+}
+
+impl<T> CancellableHandle<T> {
+    /// Extract a handle to the underlying thread.
+    pub fn thread(&self) -> &Thread {
+        self.handle.thread()
+    }
+
+    /// The token the thread checks.
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// Ask the thread to stop, without waiting for it.
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    /// Ask the thread to stop and join it, waiting for the specified duration at most.
+    ///
+    /// If the thread doesn't stop in time, the handle is given back.
+    pub fn cancel_and_join_timeout(self, wait: Duration) -> Result<thread::Result<T>, Self> {
+        self.cancel();
+        self.try_timed_join_into(wait)
+    }
+
+    /// Wait for the thread to finish and return its result, without cancelling it.
+    pub fn join(self) -> thread::Result<T> {
+        self.handle.join()
+    }
+
+    /// Give up the token and return the plain handle.
+    pub fn into_inner(self) -> TryJoinableHandle<T> {
+        self.handle
+    }
+}
+
+impl<T> TryJoinHandle for CancellableHandle<T> {
+    type Output = T;
+
+    fn try_join(&self) -> Result<(), TryJoinError> {
+        self.handle.try_join()
+    }
+
+    fn try_timed_join(&self, wait: Duration) -> Result<(), TryJoinError> {
+        self.handle.try_timed_join(wait)
+    }
+
+    fn try_join_until(&self, deadline: Instant) -> Result<(), TryJoinError> {
+        self.handle.try_join_until(deadline)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    fn try_join_into(self) -> Result<thread::Result<T>, Self> {
+        let CancellableHandle { handle, token } = self;
+        handle
+            .try_join_into()
+            .map_err(|handle| CancellableHandle { handle, token })
+    }
+
+    fn try_timed_join_into(self, wait: Duration) -> Result<thread::Result<T>, Self> {
+        let CancellableHandle { handle, token } = self;
+        handle
+            .try_timed_join_into(wait)
+            .map_err(|handle| CancellableHandle { handle, token })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cooperative_thread_stops() {
+        let t = spawn_cancellable(|token| {
+            while !token.is_cancelled() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            "stopped"
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!t.token().is_cancelled());
+        let result = t.cancel_and_join_timeout(Duration::from_secs(1));
+        assert_eq!("stopped", result.ok().unwrap().unwrap());
+    }
+
+    #[test]
+    fn stubborn_thread_is_given_back() {
+        let t = spawn_cancellable(|_token| {
+            thread::sleep(Duration::from_millis(300));
+            "ignored"
+        });
+
+        let t = t
+            .cancel_and_join_timeout(Duration::from_millis(50))
+            .unwrap_err();
+        assert!(t.token().is_cancelled());
+        assert_eq!("ignored", t.join().unwrap());
+    }
+
+    #[test]
+    fn wait_timeout_is_interrupted() {
+        let token = CancellationToken::new();
+        let their_token = token.clone();
+        let t = spawn(move || their_token.wait_timeout(Duration::from_secs(10)));
+
+        let start = Instant::now();
+        token.cancel();
+        assert!(t.join().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(!CancellationToken::new().wait_timeout(Duration::from_millis(10)));
+    }
+}
@@ -0,0 +1,132 @@
+//! Receiving a thread's result through a channel.
+
+use std::sync::mpsc;
+use std::thread;
+
+use crate::TryJoinableHandle;
+
+impl<T: Send + 'static> TryJoinableHandle<T> {
+    /// Turn the handle into a channel receiving the thread's result once it finished.
+    ///
+    /// Exactly one message is sent, as soon as the result is available,
+    /// after which the channel is disconnected.
+    /// The thread is detached instead of joined,
+    /// so it may still be exiting when the message arrives.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// let rx = thread_tryjoin::spawn(|| 42).into_receiver();
+    ///
+    /// let result = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+    /// assert_eq!(42, result.unwrap());
+    /// assert!(rx.recv().is_err());
+    /// ```
+    pub fn into_receiver(self) -> mpsc::Receiver<thread::Result<T>> {
+        let (tx, rx) = mpsc::sync_channel(1);
+        self.on_result(move |result| {
+            // Nobody to tell if the receiver is gone already.
+            let _ = tx.send(result);
+        });
+        rx
+    }
+
+    /// Like [`into_receiver`](TryJoinableHandle::into_receiver),
+    /// but returns a `crossbeam_channel::Receiver` usable with `select!`.
+    ///
+    /// Only available with the `crossbeam-channel` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// use crossbeam_channel::{select, unbounded};
+    ///
+    /// let (_tx, messages) = unbounded::<String>();
+    /// let finished = thread_tryjoin::spawn(|| 42).into_crossbeam_receiver();
+    ///
+    /// select! {
+    ///     recv(messages) -> msg => panic!("unexpected message {:?}", msg),
+    ///     recv(finished) -> result => assert_eq!(42, result.unwrap().unwrap()),
+    /// }
+    /// ```
+    #[cfg(feature = "crossbeam-channel")]
+    pub fn into_crossbeam_receiver(self) -> crossbeam_channel::Receiver<thread::Result<T>> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        self.on_result(move |result| {
+            // Nobody to tell if the receiver is gone already.
+            let _ = tx.send(result);
+        });
+        rx
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{spawn, TryJoinHandle};
+    use std::sync::mpsc::{self, RecvTimeoutError};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn fires_once_on_exit() {
+        let (release, wait) = mpsc::channel::<()>();
+        let rx = spawn(move || {
+            wait.recv().unwrap();
+            "done"
+        })
+        .into_receiver();
+
+        assert_eq!(
+            RecvTimeoutError::Timeout,
+            rx.recv_timeout(Duration::from_millis(50)).unwrap_err()
+        );
+        release.send(()).unwrap();
+        assert_eq!("done", rx.recv().unwrap().unwrap());
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn already_finished() {
+        let t = spawn(|| panic!("boom"));
+        assert!(t.try_timed_join(Duration::from_secs(1)).is_ok());
+
+        let rx = t.into_receiver();
+        assert!(rx.recv().unwrap().is_err());
+    }
+
+    #[test]
+    fn receiver_dropped() {
+        let t = spawn(|| thread::sleep(Duration::from_millis(50)));
+        drop(t.into_receiver());
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    #[cfg(feature = "crossbeam-channel")]
+    #[test]
+    fn crossbeam_select() {
+        use crossbeam_channel::{after, select, unbounded};
+
+        let (tx, messages) = unbounded();
+        let (release, wait) = mpsc::channel::<()>();
+        let finished = spawn(move || wait.recv().unwrap()).into_crossbeam_receiver();
+
+        tx.send("hello").unwrap();
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            select! {
+                recv(messages) -> msg => {
+                    received.push(msg.unwrap());
+                    release.send(()).unwrap();
+                }
+                recv(finished) -> result => {
+                    assert!(result.unwrap().is_ok());
+                    received.push("finished");
+                }
+                recv(after(Duration::from_secs(1))) -> _ => panic!("timed out"),
+            }
+        }
+        assert_eq!(vec!["hello", "finished"], received);
+    }
+}
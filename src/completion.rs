@@ -0,0 +1,195 @@
+//! Signalling the end of a thread to whoever waits for it.
+
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(target_os = "linux")]
+use std::sync::Weak;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// A registered callback.
+struct Callback {
+    run: Box<dyn FnOnce() + Send>,
+    /// Whether running it would still have any effect.
+    is_alive: Box<dyn Fn() -> bool + Send>,
+}
+
+/// A one-shot flag that is set when a spawned thread is done with its closure.
+///
+/// Checking the flag is a single atomic load,
+/// waiting for it blocks on a condition variable instead of polling.
+/// Callbacks can be registered to be notified without blocking at all.
+pub(crate) struct Completion {
+    done: AtomicBool,
+    /// Callbacks to run once the flag is set.
+    lock: Mutex<Vec<Callback>>,
+    cvar: Condvar,
+}
+
+impl Completion {
+    pub(crate) fn new() -> Completion {
+        Completion {
+            done: AtomicBool::new(false),
+            lock: Mutex::new(Vec::new()),
+            cvar: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Callback>> {
+        self.lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Mark the thread as finished and wake up everyone waiting for it.
+    pub(crate) fn complete(&self) {
+        let callbacks = {
+            let mut callbacks = self.lock();
+            self.done.store(true, Ordering::Release);
+            self.cvar.notify_all();
+            mem::take(&mut *callbacks)
+        };
+        for callback in callbacks {
+            (callback.run)();
+        }
+    }
+
+    /// Run `callback` once the flag is set.
+    ///
+    /// If it is set already, `callback` runs right away on the calling thread.
+    /// Otherwise it runs on the thread calling `complete`.
+    pub(crate) fn on_complete(&self, callback: impl FnOnce() + Send + 'static) {
+        self.register(Callback {
+            run: Box::new(callback),
+            is_alive: Box::new(|| true),
+        });
+    }
+
+    /// Run `callback` with `target` once the flag is set, if `target` is still alive by then.
+    ///
+    /// Callbacks whose target was dropped are removed whenever another one is registered,
+    /// so registering over and over while the thread runs doesn't pile them up.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn on_complete_weak<T>(&self, target: Weak<T>, callback: fn(&T))
+    where
+        T: Send + Sync + 'static,
+    {
+        let their_target = target.clone();
+        self.register(Callback {
+            run: Box::new(move || {
+                if let Some(target) = their_target.upgrade() {
+                    callback(&target);
+                }
+            }),
+            is_alive: Box::new(move || target.strong_count() > 0),
+        });
+    }
+
+    fn register(&self, callback: Callback) {
+        let mut callbacks = self.lock();
+        if self.is_complete() {
+            drop(callbacks);
+            (callback.run)();
+        } else {
+            callbacks.retain(|callback| (callback.is_alive)());
+            callbacks.push(callback);
+        }
+    }
+
+    /// How many callbacks are waiting for the flag.
+    #[cfg(test)]
+    pub(crate) fn callbacks(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+
+    /// Wait for the flag until `deadline` at most.
+    ///
+    /// Returns whether the flag is set.
+    pub(crate) fn wait_until(&self, deadline: Instant) -> bool {
+        let mut guard = self.lock();
+        while !self.is_complete() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            guard = self
+                .cvar
+                .wait_timeout(guard, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn wait_for_completion() {
+        let completion = Arc::new(Completion::new());
+        let their_completion = completion.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            their_completion.complete();
+        });
+
+        assert!(!completion.is_complete());
+        assert!(completion.wait_until(Instant::now() + Duration::from_secs(1)));
+        assert!(completion.is_complete());
+    }
+
+    #[test]
+    fn callbacks_run_once() {
+        let completion = Completion::new();
+        let (tx, rx) = mpsc::channel();
+
+        let early = tx.clone();
+        completion.on_complete(move || early.send("early").unwrap());
+        assert!(rx.try_recv().is_err());
+
+        completion.complete();
+        assert_eq!(Ok("early"), rx.try_recv());
+
+        completion.on_complete(move || tx.send("late").unwrap());
+        assert_eq!(Ok("late"), rx.try_recv());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn dead_callbacks_are_dropped() {
+        let completion = Completion::new();
+        let (tx, rx) = mpsc::channel();
+
+        for _ in 0..10 {
+            let target = Arc::new(tx.clone());
+            completion.on_complete_weak(Arc::downgrade(&target), |tx| tx.send("dropped").unwrap());
+        }
+        assert_eq!(1, completion.callbacks());
+
+        let target = Arc::new(tx);
+        completion.on_complete_weak(Arc::downgrade(&target), |tx| tx.send("alive").unwrap());
+        completion.on_complete(|| ());
+        assert_eq!(2, completion.callbacks());
+
+        completion.complete();
+        assert_eq!(Ok("alive"), rx.try_recv());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn wait_times_out() {
+        let completion = Completion::new();
+
+        let deadline = Instant::now() + Duration::from_millis(100);
+        assert!(!completion.wait_until(deadline));
+        assert!(Instant::now() >= deadline);
+    }
+}
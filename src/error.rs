@@ -0,0 +1,118 @@
+//! The error returned when a thread could not be joined.
+
+use std::error::Error;
+use std::fmt;
+use std::io::Error as IoError;
+
+/// The reason a try-join did not succeed.
+///
+/// It converts into an `io::Error` carrying the errno the pthread functions would have returned,
+/// so code that expects an `io::Error` keeps working.
+#[derive(Debug)]
+pub enum TryJoinError {
+    /// The thread has not finished yet (`EBUSY`).
+    StillRunning,
+    /// The thread did not finish before the timeout expired (`ETIMEDOUT`).
+    TimedOut,
+    /// The thread can't be joined, because it was already joined
+    /// or another thread is waiting to join it (`EINVAL`).
+    AlreadyJoined,
+    /// The thread tried to join itself (`EDEADLK`).
+    Deadlock,
+    /// Try-joining is not supported on this platform.
+    Unsupported,
+    /// Any other error reported by the operating system.
+    Os(IoError),
+}
+
+impl TryJoinError {
+    /// Map an errno returned by one of the pthread join functions.
+    pub(crate) fn from_raw_os_error(code: i32) -> TryJoinError {
+        match code {
+            libc::EBUSY => TryJoinError::StillRunning,
+            libc::ETIMEDOUT => TryJoinError::TimedOut,
+            libc::EINVAL => TryJoinError::AlreadyJoined,
+            libc::EDEADLK => TryJoinError::Deadlock,
+            code => TryJoinError::Os(IoError::from_raw_os_error(code)),
+        }
+    }
+}
+
+impl fmt::Display for TryJoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryJoinError::StillRunning => f.write_str("thread is still running"),
+            TryJoinError::TimedOut => f.write_str("timed out waiting for thread"),
+            TryJoinError::AlreadyJoined => f.write_str("thread is already joined"),
+            TryJoinError::Deadlock => f.write_str("thread tried to join itself"),
+            TryJoinError::Unsupported => f.write_str("try-join is not supported on this platform"),
+            TryJoinError::Os(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for TryJoinError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TryJoinError::Os(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<IoError> for TryJoinError {
+    fn from(err: IoError) -> TryJoinError {
+        match err.raw_os_error() {
+            Some(code) => TryJoinError::from_raw_os_error(code),
+            None => TryJoinError::Os(err),
+        }
+    }
+}
+
+impl From<TryJoinError> for IoError {
+    fn from(err: TryJoinError) -> IoError {
+        match err {
+            TryJoinError::StillRunning => IoError::from_raw_os_error(libc::EBUSY),
+            TryJoinError::TimedOut => IoError::from_raw_os_error(libc::ETIMEDOUT),
+            TryJoinError::AlreadyJoined => IoError::from_raw_os_error(libc::EINVAL),
+            TryJoinError::Deadlock => IoError::from_raw_os_error(libc::EDEADLK),
+            TryJoinError::Unsupported => IoError::from_raw_os_error(libc::ENOENT),
+            TryJoinError::Os(err) => err,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn errno_round_trip() {
+        for code in [
+            libc::EBUSY,
+            libc::ETIMEDOUT,
+            libc::EINVAL,
+            libc::EDEADLK,
+            libc::ESRCH,
+        ] {
+            let err = IoError::from(TryJoinError::from_raw_os_error(code));
+            assert_eq!(Some(code), err.raw_os_error());
+        }
+    }
+
+    #[test]
+    fn from_io_error() {
+        let err = TryJoinError::from(IoError::from_raw_os_error(libc::EBUSY));
+        assert!(matches!(err, TryJoinError::StillRunning));
+
+        let err = TryJoinError::from(IoError::other("custom"));
+        assert!(matches!(err, TryJoinError::Os(_)));
+        assert_eq!("custom", err.to_string());
+    }
+
+    #[test]
+    fn unsupported_is_enoent() {
+        let err = IoError::from(TryJoinError::Unsupported);
+        assert_eq!(Some(libc::ENOENT), err.raw_os_error());
+    }
+}
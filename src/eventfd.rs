@@ -0,0 +1,313 @@
+//! A file descriptor becoming readable when a thread finishes.
+
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::Arc;
+
+use crate::completion::Completion;
+#[cfg(any(feature = "mio", feature = "polling"))]
+use crate::TryJoinableHandle;
+
+/// An `eventfd` that becomes readable once a thread spawned by [`spawn`](crate::spawn) finished.
+///
+/// Register it with `epoll`, `poll` or `select` to wait for the thread
+/// together with sockets, timers and other file descriptors.
+/// It stays readable, as long as nobody reads from it.
+///
+/// It becomes readable as soon as the thread's result is available,
+/// which can be shortly before the thread itself exited.
+/// `join` then only blocks for that short moment.
+///
+/// Only available on Linux.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::os::fd::AsRawFd;
+/// let t = thread_tryjoin::spawn(|| 42);
+/// let fd = t.completion_fd().unwrap();
+///
+/// let mut pollfd = libc::pollfd { fd: fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+/// assert_eq!(1, unsafe { libc::poll(&mut pollfd, 1, 1000) });
+/// assert_eq!(42, t.join().unwrap());
+/// ```
+#[derive(Debug)]
+pub struct CompletionFd {
+    /// The callback writing to it only holds a weak reference,
+    /// so dropping this closes the descriptor right away, and its number is never written to after reuse.
+    fd: Arc<OwnedFd>,
+}
+
+impl CompletionFd {
+    pub(crate) fn new(completion: &Completion) -> io::Result<CompletionFd> {
+        let raw = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = Arc::new(unsafe { OwnedFd::from_raw_fd(raw) });
+
+        completion.on_complete_weak(Arc::downgrade(&fd), signal);
+        Ok(CompletionFd { fd })
+    }
+}
+
+/// Make the eventfd readable.
+fn signal(fd: &OwnedFd) {
+    let one: u64 = 1;
+    // Writing can only fail if the counter overflows, which leaves it readable anyway.
+    unsafe {
+        libc::write(
+            fd.as_raw_fd(),
+            &one as *const u64 as *const libc::c_void,
+            std::mem::size_of::<u64>(),
+        );
+    }
+}
+
+impl AsFd for CompletionFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for CompletionFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Thread completion as a `mio` event source.
+///
+/// The handle becomes readable once the thread finished, see [`CompletionFd`].
+/// Deregister it before dropping it.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// use mio::{Events, Interest, Poll, Token};
+///
+/// let mut t = thread_tryjoin::spawn(|| 42);
+///
+/// let mut poll = Poll::new().unwrap();
+/// poll.registry()
+///     .register(&mut t, Token(7), Interest::READABLE)
+///     .unwrap();
+///
+/// let mut events = Events::with_capacity(8);
+/// poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
+/// assert_eq!(Token(7), events.iter().next().unwrap().token());
+///
+/// poll.registry().deregister(&mut t).unwrap();
+/// assert_eq!(42, t.join().unwrap());
+/// ```
+#[cfg(feature = "mio")]
+impl<T> mio::event::Source for TryJoinableHandle<T> {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        let fd = self.registered_fd()?.as_raw_fd();
+        mio::unix::SourceFd(&fd).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        let fd = self.registered_fd()?.as_raw_fd();
+        mio::unix::SourceFd(&fd).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        let fd = self.registered_fd()?.as_raw_fd();
+        mio::unix::SourceFd(&fd).deregister(registry)
+    }
+}
+
+#[cfg(feature = "polling")]
+impl<T> TryJoinableHandle<T> {
+    /// Add the thread's completion to a `polling::Poller`,
+    /// reporting a readable event with `key` once the thread finished.
+    ///
+    /// Like with `Poller::add`, the event is delivered once.
+    ///
+    /// # Safety
+    ///
+    /// The handle must be deleted from the poller with
+    /// [`delete_from_poller`](TryJoinableHandle::delete_from_poller) before it is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// use polling::{Events, Poller};
+    ///
+    /// let t = thread_tryjoin::spawn(|| 42);
+    ///
+    /// let poller = Poller::new().unwrap();
+    /// unsafe { t.add_to_poller(&poller, 7).unwrap() };
+    ///
+    /// let mut events = Events::new();
+    /// poller.wait(&mut events, Some(Duration::from_secs(1))).unwrap();
+    /// assert_eq!(7, events.iter().next().unwrap().key);
+    ///
+    /// t.delete_from_poller(&poller).unwrap();
+    /// assert_eq!(42, t.join().unwrap());
+    /// ```
+    pub unsafe fn add_to_poller(&self, poller: &polling::Poller, key: usize) -> io::Result<()> {
+        poller.add(self.registered_fd()?, polling::Event::readable(key))
+    }
+
+    /// Remove the thread's completion from a `polling::Poller`.
+    pub fn delete_from_poller(&self, poller: &polling::Poller) -> io::Result<()> {
+        poller.delete(self.registered_fd()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{spawn, TryJoinHandle};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn readable(fd: &CompletionFd, timeout_ms: libc::c_int) -> bool {
+        let mut pollfd = libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ret = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        assert!(ret >= 0, "{}", io::Error::last_os_error());
+        ret == 1
+    }
+
+    #[test]
+    fn readable_on_exit() {
+        let (release, wait) = mpsc::channel::<()>();
+        let t = spawn(move || wait.recv().unwrap());
+        let fd = t.completion_fd().unwrap();
+
+        assert!(!readable(&fd, 50));
+        release.send(()).unwrap();
+        assert!(readable(&fd, 1000));
+        assert!(readable(&fd, 0));
+        assert!(t.try_timed_join(Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn already_finished() {
+        let t = spawn(|| ());
+        assert!(t.try_timed_join(Duration::from_secs(1)).is_ok());
+
+        let fd = t.completion_fd().unwrap();
+        assert!(readable(&fd, 0));
+    }
+
+    #[test]
+    fn dropped_before_exit() {
+        let (release, wait) = mpsc::channel::<()>();
+        let t = spawn(move || wait.recv().unwrap());
+        drop(t.completion_fd().unwrap());
+        let fd = t.completion_fd().unwrap();
+
+        release.send(()).unwrap();
+        assert!(readable(&fd, 1000));
+        assert!(t.join().is_ok());
+    }
+
+    #[test]
+    fn not_kept_open_by_thread() {
+        let (release, wait) = mpsc::channel::<()>();
+        let t = spawn(move || wait.recv().unwrap());
+        let fd = t.completion_fd().unwrap();
+        assert_eq!(1, Arc::strong_count(&fd.fd));
+
+        drop(fd);
+        release.send(()).unwrap();
+        assert!(t.join().is_ok());
+    }
+
+    #[test]
+    fn dropped_fds_dont_pile_up() {
+        let (release, wait) = mpsc::channel::<()>();
+        let t = spawn(move || wait.recv().unwrap());
+        for _ in 0..10 {
+            drop(t.completion_fd().unwrap());
+        }
+        let fd = t.completion_fd().unwrap();
+        assert_eq!(1, t.completion().callbacks());
+
+        release.send(()).unwrap();
+        assert!(readable(&fd, 1000));
+        assert!(t.join().is_ok());
+    }
+
+    #[cfg(feature = "mio")]
+    #[test]
+    fn mio_event_on_exit() {
+        use mio::{Events, Interest, Poll, Token};
+
+        let (release, wait) = mpsc::channel::<()>();
+        let mut t = spawn(move || wait.recv().unwrap());
+        let mut poll = Poll::new().unwrap();
+        poll.registry()
+            .register(&mut t, Token(1), Interest::READABLE)
+            .unwrap();
+
+        let mut events = Events::with_capacity(8);
+        poll.poll(&mut events, Some(Duration::from_millis(50)))
+            .unwrap();
+        assert!(events.is_empty());
+
+        release.send(()).unwrap();
+        poll.poll(&mut events, Some(Duration::from_secs(1)))
+            .unwrap();
+        let event = events.iter().next().unwrap();
+        assert_eq!(Token(1), event.token());
+        assert!(event.is_readable());
+
+        poll.registry()
+            .reregister(&mut t, Token(2), Interest::READABLE)
+            .unwrap();
+        poll.poll(&mut events, Some(Duration::from_secs(1)))
+            .unwrap();
+        assert_eq!(Token(2), events.iter().next().unwrap().token());
+
+        poll.registry().deregister(&mut t).unwrap();
+        assert!(t.join().is_ok());
+    }
+
+    #[cfg(feature = "polling")]
+    #[test]
+    fn polling_event_on_exit() {
+        use polling::{Events, Poller};
+
+        let (release, wait) = mpsc::channel::<()>();
+        let t = spawn(move || wait.recv().unwrap());
+        let poller = Poller::new().unwrap();
+        unsafe { t.add_to_poller(&poller, 3).unwrap() };
+
+        let mut events = Events::new();
+        poller
+            .wait(&mut events, Some(Duration::from_millis(50)))
+            .unwrap();
+        assert!(events.is_empty());
+
+        release.send(()).unwrap();
+        poller
+            .wait(&mut events, Some(Duration::from_secs(1)))
+            .unwrap();
+        let event = events.iter().next().unwrap();
+        assert_eq!(3, event.key);
+        assert!(event.readable);
+
+        t.delete_from_poller(&poller).unwrap();
+        assert!(t.join().is_ok());
+    }
+}
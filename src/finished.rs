@@ -0,0 +1,148 @@
+//! The outcome of a finished thread.
+
+use std::any::Any;
+use std::fmt;
+use std::thread;
+
+/// How a thread finished: by returning a value or by panicking.
+///
+/// This is the same information as `std::thread::Result`,
+/// but makes it easy to tell a crashed thread apart and to get at its panic message.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::thread;
+/// use thread_tryjoin::{Finished, TryJoinHandle};
+///
+/// let t = thread_tryjoin::spawn(|| -> u32 { panic!("worker crashed") });
+/// # thread::sleep(std::time::Duration::from_millis(100));
+///
+/// match t.try_join_finished() {
+///     Ok(Finished::Returned(value)) => println!("worker returned {}", value),
+///     Ok(finished) => assert_eq!(Some("worker crashed"), finished.panic_message()),
+///     Err(_handle) => println!("worker is still running"),
+/// }
+/// ```
+pub enum Finished<T> {
+    /// The thread returned a value.
+    Returned(T),
+    /// The thread panicked with the given payload.
+    Panicked(Box<dyn Any + Send + 'static>),
+}
+
+impl<T> Finished<T> {
+    /// Whether the thread panicked.
+    pub fn is_panicked(&self) -> bool {
+        matches!(self, Finished::Panicked(_))
+    }
+
+    /// The message the thread panicked with.
+    ///
+    /// Returns `None` if the thread didn't panic,
+    /// or if the panic payload is neither a `&str` nor a `String`.
+    pub fn panic_message(&self) -> Option<&str> {
+        match self {
+            Finished::Returned(_) => None,
+            Finished::Panicked(payload) => payload_message(payload.as_ref()),
+        }
+    }
+
+    /// Convert back into the result `JoinHandle::join` would have returned.
+    pub fn into_result(self) -> thread::Result<T> {
+        match self {
+            Finished::Returned(value) => Ok(value),
+            Finished::Panicked(payload) => Err(payload),
+        }
+    }
+}
+
+/// The message of a panic payload, if it is a `&str` or a `String`.
+pub(crate) fn payload_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        Some(msg)
+    } else {
+        payload.downcast_ref::<String>().map(String::as_str)
+    }
+}
+
+impl<T> From<thread::Result<T>> for Finished<T> {
+    fn from(result: thread::Result<T>) -> Finished<T> {
+        match result {
+            Ok(value) => Finished::Returned(value),
+            Err(payload) => Finished::Panicked(payload),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Finished<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finished::Returned(value) => f.debug_tuple("Returned").field(value).finish(),
+            Finished::Panicked(payload) => match payload_message(payload.as_ref()) {
+                Some(msg) => f.debug_tuple("Panicked").field(&msg).finish(),
+                None => f.write_str("Panicked(..)"),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{spawn, TryJoinHandle};
+    use std::time::Duration;
+
+    #[test]
+    fn returned() {
+        let t = spawn(|| 42);
+
+        let finished = t
+            .try_timed_join_into(Duration::from_secs(1))
+            .ok()
+            .map(Finished::from)
+            .unwrap();
+        assert!(!finished.is_panicked());
+        assert_eq!(None, finished.panic_message());
+        assert_eq!(42, finished.into_result().unwrap());
+    }
+
+    #[test]
+    fn panicked_with_str() {
+        let t = spawn(|| panic!("boom"));
+        thread::sleep(Duration::from_millis(100));
+
+        let finished: Finished<()> = t.try_join_finished().ok().unwrap();
+        assert!(finished.is_panicked());
+        assert_eq!(Some("boom"), finished.panic_message());
+        assert_eq!(r#"Panicked("boom")"#, format!("{:?}", finished));
+    }
+
+    #[test]
+    fn panicked_with_string() {
+        let t = thread::spawn(|| panic!("boom {}", 42));
+        thread::sleep(Duration::from_millis(100));
+
+        let finished: Finished<()> = t.try_join_finished().ok().unwrap();
+        assert_eq!(Some("boom 42"), finished.panic_message());
+    }
+
+    #[test]
+    fn panicked_with_other_payload() {
+        let t = spawn(|| std::panic::panic_any(42));
+        thread::sleep(Duration::from_millis(100));
+
+        let finished: Finished<()> = t.try_join_finished().ok().unwrap();
+        assert!(finished.is_panicked());
+        assert_eq!(None, finished.panic_message());
+        assert_eq!("Panicked(..)", format!("{:?}", finished));
+    }
+
+    #[test]
+    fn still_running() {
+        let t = spawn(|| thread::sleep(Duration::from_millis(300)));
+
+        let t = t.try_join_finished().unwrap_err();
+        assert!(t.join().is_ok());
+    }
+}
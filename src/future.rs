@@ -0,0 +1,210 @@
+//! Awaiting a thread from async code.
+
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::{TryJoinHandle, TryJoinableHandle};
+
+/// A future resolving to the result of a thread once it finished.
+///
+/// The thread wakes the task when it is done, so the future isn't polled in a busy loop
+/// and no executor thread blocks waiting for it.
+/// This works with any executor.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::future::Future;
+/// # use std::pin::pin;
+/// # use std::sync::Arc;
+/// # use std::task::{Context, Poll, Wake};
+/// # use std::thread::{self, Thread};
+/// # struct ThreadWaker(Thread);
+/// # impl Wake for ThreadWaker {
+/// #     fn wake(self: Arc<Self>) {
+/// #         self.0.unpark();
+/// #     }
+/// # }
+/// # fn block_on<F: Future>(fut: F) -> F::Output {
+/// #     let mut fut = pin!(fut);
+/// #     let waker = Arc::new(ThreadWaker(thread::current())).into();
+/// #     let mut cx = Context::from_waker(&waker);
+/// #     loop {
+/// #         match fut.as_mut().poll(&mut cx) {
+/// #             Poll::Ready(res) => return res,
+/// #             Poll::Pending => thread::park(),
+/// #         }
+/// #     }
+/// # }
+/// let t = thread_tryjoin::spawn(|| 6 * 7);
+///
+/// let value = block_on(async { t.await.unwrap() });
+/// assert_eq!(42, value);
+/// ```
+pub struct JoinFuture<T> {
+    handle: Option<TryJoinableHandle<T>>,
+    /// The waker of the task that polled last, woken by the thread when it is done.
+    waker: Arc<Mutex<Option<Waker>>>,
+    registered: bool,
+}
+
+impl<T> JoinFuture<T> {
+    /// Wait for the thread of `handle` asynchronously.
+    pub fn new(handle: TryJoinableHandle<T>) -> JoinFuture<T> {
+        JoinFuture {
+            handle: Some(handle),
+            waker: Arc::new(Mutex::new(None)),
+            registered: false,
+        }
+    }
+}
+
+impl<T> Future for JoinFuture<T> {
+    type Output = thread::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let handle = this
+            .handle
+            .as_ref()
+            .expect("JoinFuture polled after completion");
+
+        // Store the waker before checking, so a thread finishing in between still wakes us.
+        {
+            let mut waker = this.waker.lock().unwrap_or_else(|e| e.into_inner());
+            match &mut *waker {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                waker => *waker = Some(cx.waker().clone()),
+            }
+        }
+        if !this.registered {
+            this.registered = true;
+            let waker = this.waker.clone();
+            handle.completion().on_complete(move || {
+                let waker = waker.lock().unwrap_or_else(|e| e.into_inner()).take();
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            });
+        }
+
+        if handle.is_finished() {
+            // The thread is just about to exit, so joining it only blocks very briefly.
+            let handle = this.handle.take().unwrap();
+            Poll::Ready(handle.join())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> IntoFuture for TryJoinableHandle<T> {
+    type Output = thread::Result<T>;
+    type IntoFuture = JoinFuture<T>;
+
+    fn into_future(self) -> JoinFuture<T> {
+        JoinFuture::new(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spawn;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+    use std::thread::Thread;
+    use std::time::Duration;
+
+    struct ThreadWaker {
+        thread: Thread,
+        wakes: AtomicUsize,
+    }
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.wakes.fetch_add(1, Ordering::SeqCst);
+            self.thread.unpark();
+        }
+    }
+
+    /// Run `fut` to completion on the current thread, returning its output and the number of polls.
+    fn block_on<F: Future>(fut: F) -> (F::Output, usize) {
+        let mut fut = pin!(fut);
+        let waker = Arc::new(ThreadWaker {
+            thread: thread::current(),
+            wakes: AtomicUsize::new(0),
+        });
+        let task_waker = Waker::from(waker.clone());
+        let mut cx = Context::from_waker(&task_waker);
+
+        let mut polls = 0;
+        loop {
+            polls += 1;
+            if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+                return (output, polls);
+            }
+            // Spurious unparks are possible, only poll again once woken.
+            let wakes = waker.wakes.load(Ordering::SeqCst);
+            while waker.wakes.load(Ordering::SeqCst) == wakes {
+                thread::park();
+            }
+        }
+    }
+
+    #[test]
+    fn await_value() {
+        let t = spawn(|| {
+            thread::sleep(Duration::from_millis(200));
+            "ok"
+        });
+
+        let (result, polls) = block_on(t.into_future());
+        assert_eq!("ok", result.unwrap());
+        assert_eq!(2, polls);
+    }
+
+    #[test]
+    fn await_finished_thread() {
+        let t = spawn(|| "ok");
+        thread::sleep(Duration::from_millis(100));
+
+        let (result, polls) = block_on(async { t.await });
+        assert_eq!("ok", result.unwrap());
+        assert_eq!(1, polls);
+    }
+
+    #[test]
+    fn await_panic() {
+        let t = spawn(|| panic!("boom"));
+
+        let (result, _) = block_on(JoinFuture::new(t));
+        let err = result.unwrap_err();
+        assert_eq!(Some(&"boom"), err.downcast_ref::<&str>());
+    }
+
+    #[test]
+    fn await_many() {
+        let handles: Vec<_> = (0..4u64)
+            .map(|i| {
+                spawn(move || {
+                    thread::sleep(Duration::from_millis(50 * i));
+                    i
+                })
+            })
+            .collect();
+
+        let (sum, _) = block_on(async {
+            let mut sum = 0;
+            for t in handles {
+                sum += t.await.unwrap();
+            }
+            sum
+        });
+        assert_eq!(6, sum);
+    }
+}
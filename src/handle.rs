@@ -0,0 +1,517 @@
+//! A thread handle that can be try-joined safely.
+
+use std::io;
+#[cfg(all(target_os = "linux", not(feature = "portable")))]
+use std::os::unix::thread::JoinHandleExt;
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(target_os = "linux")]
+use std::sync::mpsc;
+#[cfg(all(
+    target_os = "linux",
+    any(feature = "mio", feature = "polling", feature = "tokio")
+))]
+use std::sync::OnceLock;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use crate::completion::Completion;
+#[cfg(all(target_os = "linux", not(feature = "portable")))]
+use crate::linux::Native;
+#[cfg(not(all(target_os = "linux", not(feature = "portable"))))]
+use crate::portable::Native;
+#[cfg(target_os = "linux")]
+use crate::CompletionFd;
+use crate::{ThreadError, ThreadInfo, TryJoinError, TryJoinHandle};
+
+/// Where the spawned thread stores its result.
+struct Packet<T> {
+    result: Mutex<Option<thread::Result<T>>>,
+    /// Completed once `result` is filled in.
+    completion: Arc<Completion>,
+    /// When the thread was spawned.
+    #[cfg(target_os = "linux")]
+    spawned: Instant,
+}
+
+/// Spawn a new thread, returning a [`TryJoinableHandle`] for it.
+///
+/// This works like `std::thread::spawn`, including panicking if the thread can't be created.
+/// Use a [`Builder`] to name the thread, set its stack size or handle spawn errors.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::{Duration, Instant};
+/// # use std::thread;
+/// use thread_tryjoin::TryJoinHandle;
+///
+/// let t = thread_tryjoin::spawn(|| "ok");
+/// thread::sleep(Duration::from_millis(100));
+///
+/// assert!(t.try_join().is_ok());
+/// assert_eq!("ok", t.join().unwrap());
+/// ```
+pub fn spawn<F, T>(f: F) -> TryJoinableHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Builder::new().spawn(f).expect("failed to spawn thread")
+}
+
+/// Thread factory to configure the properties of a new thread,
+/// like `std::thread::Builder`, but spawning a [`TryJoinableHandle`].
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// use thread_tryjoin::{Builder, TryJoinHandle};
+///
+/// let t = Builder::new()
+///     .name("worker".into())
+///     .stack_size(64 * 1024)
+///     .spawn(|| 42)
+///     .unwrap();
+///
+/// let info = t.try_timed_join_with_info(Duration::from_secs(1)).unwrap();
+/// assert_eq!(Some("worker"), info.name());
+/// assert_eq!(42, t.join().unwrap());
+/// ```
+#[derive(Debug)]
+pub struct Builder {
+    inner: thread::Builder,
+}
+
+impl Builder {
+    /// Generate the base configuration for spawning a thread.
+    pub fn new() -> Builder {
+        Builder {
+            inner: thread::Builder::new(),
+        }
+    }
+
+    /// Name the thread-to-be.
+    pub fn name(self, name: String) -> Builder {
+        Builder {
+            inner: self.inner.name(name),
+        }
+    }
+
+    /// Set the size of the stack (in bytes) for the new thread.
+    pub fn stack_size(self, size: usize) -> Builder {
+        Builder {
+            inner: self.inner.stack_size(size),
+        }
+    }
+
+    /// Spawn a new thread by taking ownership of the `Builder`,
+    /// and return an `io::Result` to its [`TryJoinableHandle`].
+    pub fn spawn<F, T>(self, f: F) -> io::Result<TryJoinableHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let packet = Arc::new(Packet {
+            result: Mutex::new(None),
+            completion: Arc::new(Completion::new()),
+            #[cfg(target_os = "linux")]
+            spawned: Instant::now(),
+        });
+        let their_packet = packet.clone();
+        #[cfg(target_os = "linux")]
+        let (ids_tx, ids_rx) = mpsc::sync_channel(1);
+
+        let handle = self.inner.spawn(move || {
+            #[cfg(target_os = "linux")]
+            let _ = ids_tx.send(unsafe { (libc::gettid(), libc::pthread_self() as usize) });
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            *their_packet.result.lock().unwrap() = Some(result);
+            their_packet.completion.complete();
+        })?;
+        let thread = handle.thread().clone();
+        // The kernel thread ID is only known to the thread itself,
+        // so wait for it to be sent over before handing out the handle.
+        #[cfg(target_os = "linux")]
+        let ids = ids_rx.recv().expect("spawned thread didn't send its ids");
+
+        #[cfg(all(target_os = "linux", not(feature = "portable")))]
+        // On musl `libc` defines `pthread_t` as a pointer, while std uses an integer.
+        let native = Native::new(handle.into_pthread_t() as libc::pthread_t);
+        #[cfg(not(all(target_os = "linux", not(feature = "portable"))))]
+        let native = Native::new(handle, packet.completion.clone());
+
+        Ok(TryJoinableHandle {
+            native,
+            thread,
+            packet,
+            #[cfg(target_os = "linux")]
+            ids,
+            #[cfg(all(
+                target_os = "linux",
+                any(feature = "mio", feature = "polling", feature = "tokio")
+            ))]
+            registered_fd: OnceLock::new(),
+            #[cfg(all(target_os = "linux", feature = "tokio"))]
+            notify: OnceLock::new(),
+        })
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+/// An owned permission to join on a thread, which can also be try-joined.
+///
+/// Unlike `std::thread::JoinHandle` this handle records a successful try-join.
+/// A later [`join`](TryJoinableHandle::join) then returns the thread's result
+/// without joining the already reaped thread again,
+/// and dropping the handle doesn't detach it either.
+pub struct TryJoinableHandle<T> {
+    native: Native,
+    thread: Thread,
+    packet: Arc<Packet<T>>,
+    /// The thread's kernel ID and `pthread_t`.
+    #[cfg(target_os = "linux")]
+    ids: (libc::pid_t, usize),
+    /// The descriptor registered with a reactor, created on first registration.
+    #[cfg(all(
+        target_os = "linux",
+        any(feature = "mio", feature = "polling", feature = "tokio")
+    ))]
+    registered_fd: OnceLock<CompletionFd>,
+    /// Woken once the thread finished, created on first use.
+    #[cfg(all(target_os = "linux", feature = "tokio"))]
+    notify: OnceLock<Arc<tokio::sync::Notify>>,
+}
+
+impl<T> TryJoinableHandle<T> {
+    /// Extract a handle to the underlying thread.
+    pub fn thread(&self) -> &Thread {
+        &self.thread
+    }
+
+    /// The thread's name and kernel thread ID, for logging.
+    pub fn info(&self) -> ThreadInfo {
+        #[cfg(target_os = "linux")]
+        let tid = Some(self.ids.0);
+        #[cfg(not(target_os = "linux"))]
+        let tid = None;
+
+        ThreadInfo {
+            name: self.thread.name().map(String::from),
+            tid,
+        }
+    }
+
+    /// Like [`try_join`](TryJoinHandle::try_join), but tells which thread was joined, or not.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use std::thread;
+    /// let t = thread_tryjoin::spawn(|| thread::sleep(Duration::from_millis(200)));
+    ///
+    /// let err = t.try_join_with_info().unwrap_err();
+    /// assert!(err.to_string().starts_with("thread '<unnamed>' (tid "));
+    /// ```
+    pub fn try_join_with_info(&self) -> Result<ThreadInfo, ThreadError> {
+        self.with_info(self.try_join())
+    }
+
+    /// Like [`try_timed_join`](TryJoinHandle::try_timed_join),
+    /// but tells which thread was joined, or not.
+    pub fn try_timed_join_with_info(&self, wait: Duration) -> Result<ThreadInfo, ThreadError> {
+        self.with_info(self.try_timed_join(wait))
+    }
+
+    fn with_info(&self, result: Result<(), TryJoinError>) -> Result<ThreadInfo, ThreadError> {
+        match result {
+            Ok(()) => Ok(self.info()),
+            Err(err) => Err(ThreadError::new(self.info(), err)),
+        }
+    }
+
+    /// Create a file descriptor that becomes readable once the thread finished.
+    ///
+    /// Every call creates a new descriptor, so keep it around instead of
+    /// calling this again on every iteration of an event loop.
+    /// See [`CompletionFd`] for details.
+    #[cfg(target_os = "linux")]
+    pub fn completion_fd(&self) -> io::Result<CompletionFd> {
+        CompletionFd::new(&self.packet.completion)
+    }
+
+    /// The descriptor to register with a reactor, the same one every time.
+    #[cfg(all(
+        target_os = "linux",
+        any(feature = "mio", feature = "polling", feature = "tokio")
+    ))]
+    pub(crate) fn registered_fd(&self) -> io::Result<&CompletionFd> {
+        if let Some(fd) = self.registered_fd.get() {
+            return Ok(fd);
+        }
+        let _ = self.registered_fd.set(self.completion_fd()?);
+        Ok(self.registered_fd.get().unwrap())
+    }
+
+    /// A `Notify` getting a permit once the thread finished, the same one every time.
+    #[cfg(all(target_os = "linux", feature = "tokio"))]
+    pub(crate) fn notify(&self) -> &tokio::sync::Notify {
+        self.notify.get_or_init(|| {
+            let notify = Arc::new(tokio::sync::Notify::new());
+            let their_notify = notify.clone();
+            self.packet
+                .completion
+                .on_complete(move || their_notify.notify_one());
+            notify
+        })
+    }
+
+    /// The completion signalled by the thread when its result is available.
+    pub(crate) fn completion(&self) -> &Completion {
+        &self.packet.completion
+    }
+
+    /// How long ago the thread was spawned.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn running_for(&self) -> Duration {
+        self.packet.spawned.elapsed()
+    }
+
+    /// The thread's kernel ID and `pthread_t`.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn native_ids(&self) -> (libc::pid_t, usize) {
+        self.ids
+    }
+
+    /// Wait for the thread to finish and return its result.
+    ///
+    /// If the thread was already try-joined successfully, this returns immediately.
+    pub fn join(self) -> thread::Result<T> {
+        let TryJoinableHandle { native, packet, .. } = self;
+        if let Err(err) = native.join() {
+            panic!("failed to join thread: {}", err);
+        }
+        let result = packet.result.lock().unwrap().take();
+        result.expect("thread finished without a result")
+    }
+
+    /// Hand the thread's result to `f` as soon as it is available, without joining.
+    ///
+    /// `f` runs on the spawned thread, or right away if the thread already finished.
+    /// The thread is detached, unless it was try-joined already.
+    pub(crate) fn on_result<F>(self, f: F)
+    where
+        F: FnOnce(thread::Result<T>) + Send + 'static,
+        T: Send + 'static,
+    {
+        let TryJoinableHandle { packet, .. } = self;
+        let their_packet = packet.clone();
+        packet.completion.on_complete(move || {
+            let result = their_packet.result.lock().unwrap().take();
+            f(result.expect("thread finished without a result"));
+        });
+    }
+}
+
+impl<T> TryJoinHandle for TryJoinableHandle<T> {
+    type Output = T;
+
+    fn try_join(&self) -> Result<(), TryJoinError> {
+        self.native.try_join().map(drop)
+    }
+
+    fn try_timed_join(&self, wait: Duration) -> Result<(), TryJoinError> {
+        self.native.timed_join(wait).map(drop)
+    }
+
+    fn try_join_until(&self, deadline: Instant) -> Result<(), TryJoinError> {
+        self.native.join_until(deadline).map(drop)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.packet.completion.is_complete()
+    }
+
+    fn try_join_into(self) -> Result<thread::Result<T>, Self> {
+        match self.try_join() {
+            Ok(()) => Ok(self.join()),
+            Err(_) => Err(self),
+        }
+    }
+
+    fn try_timed_join_into(self, wait: Duration) -> Result<thread::Result<T>, Self> {
+        match self.try_timed_join(wait) {
+            Ok(()) => Ok(self.join()),
+            Err(_) => Err(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn try_join_then_join() {
+        let t = spawn(|| String::from("ok"));
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(t.try_join().is_ok());
+        // Joining again must not touch the reaped pthread.
+        assert!(t.try_join().is_ok());
+        assert_eq!("ok", t.join().unwrap());
+    }
+
+    #[test]
+    fn timed_join_then_join() {
+        let t = spawn(|| {
+            thread::sleep(Duration::from_millis(100));
+            vec![1, 2, 3]
+        });
+
+        assert!(t.try_timed_join(Duration::from_millis(500)).is_ok());
+        assert_eq!(vec![1, 2, 3], t.join().unwrap());
+    }
+
+    #[test]
+    fn shared_deadline() {
+        let deadline = Instant::now() + Duration::from_millis(300);
+        let handles: Vec<_> = (0..4u64)
+            .map(|i| spawn(move || thread::sleep(Duration::from_millis(i * 200))))
+            .collect();
+
+        let joined: Vec<_> = handles
+            .iter()
+            .map(|t| t.try_join_until(deadline).is_ok())
+            .collect();
+        assert_eq!(vec![true, true, false, false], joined);
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    fn failing_try_join_then_join() {
+        let t = spawn(|| {
+            thread::sleep(Duration::from_millis(300));
+            "ok"
+        });
+
+        let err = t.try_join().unwrap_err();
+        assert!(matches!(err, TryJoinError::StillRunning));
+        assert_eq!("ok", t.join().unwrap());
+    }
+
+    #[test]
+    fn drop_after_try_join() {
+        let t = spawn(|| "ok");
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(t.try_join().is_ok());
+        drop(t);
+    }
+
+    #[test]
+    fn drop_running_thread() {
+        let t = spawn(|| thread::sleep(Duration::from_millis(100)));
+        drop(t);
+    }
+
+    #[test]
+    fn try_join_into_then_value() {
+        let mut t = spawn(|| 42);
+
+        let value = loop {
+            match t.try_join_into() {
+                Ok(result) => break result.unwrap(),
+                Err(handle) => t = handle,
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(42, value);
+    }
+
+    #[test]
+    fn is_finished_then_join() {
+        let t = spawn(|| {
+            thread::sleep(Duration::from_millis(100));
+            "ok"
+        });
+
+        assert!(!t.is_finished());
+        thread::sleep(Duration::from_millis(300));
+        for _ in 0..3 {
+            assert!(t.is_finished());
+        }
+        assert_eq!("ok", t.join().unwrap());
+    }
+
+    #[test]
+    fn is_finished_after_panic() {
+        let t = spawn(|| panic!("boom"));
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(t.is_finished());
+        assert!(t.join().is_err());
+    }
+
+    #[test]
+    fn info_on_success_and_failure() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let t = spawn(move || {
+            #[cfg(target_os = "linux")]
+            tx.send(unsafe { libc::gettid() }).unwrap();
+            thread::sleep(Duration::from_millis(200));
+        });
+
+        // Known right after spawning, without waiting for the thread to get going.
+        let err = t.try_join_with_info().unwrap_err();
+        assert!(matches!(err.error(), TryJoinError::StillRunning));
+        assert_eq!(None, err.info().name());
+        #[cfg(target_os = "linux")]
+        assert_eq!(Some(rx.recv().unwrap()), err.info().tid());
+        #[cfg(not(target_os = "linux"))]
+        drop((tx, rx));
+
+        let info = t.try_timed_join_with_info(Duration::from_secs(1)).unwrap();
+        assert_eq!(err.info(), &info);
+        assert!(t.join().is_ok());
+    }
+
+    #[test]
+    fn builder_names_thread() {
+        let t = Builder::new()
+            .name("named".to_string())
+            .spawn(|| thread::current().name().map(String::from))
+            .unwrap();
+
+        assert_eq!(Some("named"), t.thread().name());
+        assert_eq!(Some("named"), t.info().name());
+        assert_eq!(Some("named".to_string()), t.join().unwrap());
+    }
+
+    #[test]
+    fn builder_reports_spawn_error() {
+        let err = Builder::new()
+            .stack_size(usize::MAX)
+            .spawn(|| ())
+            .err()
+            .unwrap();
+        // No stack size can be that large, so creating the thread fails with `EINVAL`.
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn panic_is_propagated() {
+        let t = spawn(|| panic!("boom"));
+
+        assert!(t.try_timed_join(Duration::from_secs(1)).is_ok());
+        let err = t.join().unwrap_err();
+        assert_eq!(Some(&"boom"), err.downcast_ref::<&str>());
+    }
+}
@@ -0,0 +1,120 @@
+//! Telling threads apart in logs.
+
+use std::error::Error;
+use std::fmt;
+use std::io::Error as IoError;
+
+use crate::TryJoinError;
+
+/// The name and kernel thread ID of a thread spawned by [`spawn`](crate::spawn).
+///
+/// Unlike a `pthread_t`, these can be matched against `ps -L`, `top -H` or `/proc`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThreadInfo {
+    pub(crate) name: Option<String>,
+    pub(crate) tid: Option<i32>,
+}
+
+impl ThreadInfo {
+    /// The thread's name, if it has one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The kernel's ID for the thread, as returned by `gettid`.
+    ///
+    /// Only known on Linux, where it is captured when the thread is spawned.
+    pub fn tid(&self) -> Option<i32> {
+        self.tid
+    }
+}
+
+impl fmt::Display for ThreadInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "thread '{}'", self.name().unwrap_or("<unnamed>"))?;
+        if let Some(tid) = self.tid {
+            write!(f, " (tid {})", tid)?;
+        }
+        Ok(())
+    }
+}
+
+/// A [`TryJoinError`] together with the thread it happened for.
+#[derive(Debug)]
+pub struct ThreadError {
+    info: ThreadInfo,
+    error: TryJoinError,
+}
+
+impl ThreadError {
+    pub(crate) fn new(info: ThreadInfo, error: TryJoinError) -> ThreadError {
+        ThreadError { info, error }
+    }
+
+    /// The thread that couldn't be joined.
+    pub fn info(&self) -> &ThreadInfo {
+        &self.info
+    }
+
+    /// Why the thread couldn't be joined.
+    pub fn error(&self) -> &TryJoinError {
+        &self.error
+    }
+
+    /// Drop the thread's info and return the plain error.
+    pub fn into_error(self) -> TryJoinError {
+        self.error
+    }
+}
+
+impl fmt::Display for ThreadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.info, self.error)
+    }
+}
+
+impl Error for ThreadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<ThreadError> for IoError {
+    fn from(err: ThreadError) -> IoError {
+        err.error.into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let info = ThreadInfo {
+            name: Some("worker".to_string()),
+            tid: Some(1234),
+        };
+        let err = ThreadError::new(info, TryJoinError::TimedOut);
+        assert_eq!(
+            "thread 'worker' (tid 1234): timed out waiting for thread",
+            err.to_string()
+        );
+
+        let unnamed = ThreadInfo {
+            name: None,
+            tid: None,
+        };
+        assert_eq!("thread '<unnamed>'", unnamed.to_string());
+    }
+
+    #[test]
+    fn into_io_error() {
+        let info = ThreadInfo {
+            name: None,
+            tid: None,
+        };
+        let err = IoError::from(ThreadError::new(info, TryJoinError::StillRunning));
+        assert_eq!(Some(libc::EBUSY), err.raw_os_error());
+    }
+}
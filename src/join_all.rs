@@ -0,0 +1,132 @@
+//! Joining many threads within one overall timeout.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::TryJoinHandle;
+
+/// The outcome of [`join_all_timeout`].
+pub struct JoinAll<H: TryJoinHandle> {
+    /// The threads that finished in time, with their position in the input and their result.
+    pub finished: Vec<(usize, thread::Result<H::Output>)>,
+    /// The threads still running when the timeout expired,
+    /// with their position in the input and their handle.
+    pub pending: Vec<(usize, H)>,
+}
+
+impl<H: TryJoinHandle> JoinAll<H> {
+    /// Whether all threads finished in time.
+    pub fn is_complete(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Join all threads, waiting for the specified duration at most in total.
+///
+/// All handles share a single deadline, so the overall wait is bounded by `wait`
+/// no matter how many handles there are.
+/// Threads that finished are joined and their results returned,
+/// the handles of threads still running are given back.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use std::thread;
+/// use thread_tryjoin::join_all_timeout;
+///
+/// let handles = vec![
+///     thread::spawn(|| 1),
+///     thread::spawn(|| {
+///         thread::sleep(Duration::from_millis(500));
+///         2
+///     }),
+/// ];
+///
+/// let joined = join_all_timeout(handles, Duration::from_millis(100));
+/// assert_eq!(0, joined.finished[0].0);
+/// assert_eq!(1, joined.pending[0].0);
+/// ```
+pub fn join_all_timeout<H, I>(handles: I, wait: Duration) -> JoinAll<H>
+where
+    H: TryJoinHandle,
+    I: IntoIterator<Item = H>,
+{
+    let deadline = Instant::now() + wait;
+    let mut joined = JoinAll {
+        finished: Vec::new(),
+        pending: Vec::new(),
+    };
+
+    for (idx, handle) in handles.into_iter().enumerate() {
+        // Once the deadline passed this doesn't wait anymore, but still picks up finished threads.
+        if handle.try_join_until(deadline).is_err() {
+            joined.pending.push((idx, handle));
+            continue;
+        }
+        match handle.try_join_into() {
+            Ok(result) => joined.finished.push((idx, result)),
+            Err(handle) => joined.pending.push((idx, handle)),
+        }
+    }
+
+    joined
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spawn;
+
+    #[test]
+    fn shared_deadline() {
+        let handles: Vec<_> = [100u64, 600, 100, 600, 200]
+            .into_iter()
+            .map(|ms| {
+                spawn(move || {
+                    thread::sleep(Duration::from_millis(ms));
+                    ms
+                })
+            })
+            .collect();
+
+        let start = Instant::now();
+        let joined = join_all_timeout(handles, Duration::from_millis(300));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(300));
+        assert!(elapsed < Duration::from_millis(500));
+        assert!(!joined.is_complete());
+
+        let finished: Vec<_> = joined
+            .finished
+            .into_iter()
+            .map(|(idx, result)| (idx, result.unwrap()))
+            .collect();
+        assert_eq!(vec![(0, 100), (2, 100), (4, 200)], finished);
+
+        let pending: Vec<_> = joined.pending.iter().map(|(idx, _)| *idx).collect();
+        assert_eq!(vec![1, 3], pending);
+        for (_, handle) in joined.pending {
+            assert_eq!(600, handle.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn all_finished() {
+        let handles: Vec<_> = (0..3).map(|i| thread::spawn(move || i)).collect();
+
+        let joined = join_all_timeout(handles, Duration::from_secs(1));
+        assert!(joined.is_complete());
+        assert_eq!(3, joined.finished.len());
+    }
+
+    #[test]
+    fn panicked_thread_is_finished() {
+        let handles = vec![spawn(|| panic!("boom")), spawn(|| ())];
+
+        let joined = join_all_timeout(handles, Duration::from_secs(1));
+        assert!(joined.is_complete());
+        assert!(joined.finished[0].1.is_err());
+        assert!(joined.finished[1].1.is_ok());
+    }
+}
@@ -8,9 +8,34 @@
 //! [`pthread_tryjoin_np`](http://linux.die.net/man/3/pthread_tryjoin_np)
 //!
 //! This library provides convenient access through a `try_join` method on `JoinHandle`.
-//! It only works on Linux though.
-//! It uses [`JoinHandleExt`](https://doc.rust-lang.org/stable/std/os/unix/thread/trait.JoinHandleExt.html)
-//! to get to the underlying `pthread_t` handle.
+//!
+//! A `std::thread::JoinHandle` joins its pthread itself, so reaping it behind its back
+//! would make the later `join` (or drop) undefined behaviour.
+//! For `JoinHandle` the `try_join` methods therefore only check whether the thread finished.
+//! The same goes for the `ScopedJoinHandle` of threads spawned with `std::thread::scope`.
+//! Threads started with [`spawn`] or a [`Builder`] return a [`TryJoinableHandle`] instead,
+//! which owns the underlying `pthread_t` and remembers when `pthread_tryjoin_np` reaped it.
+//!
+//! On other platforms, or on Linux with the `portable` feature enabled,
+//! [`TryJoinableHandle`] doesn't use the pthread API at all:
+//! the spawned thread signals its completion through an atomic flag and a condition variable,
+//! which the try-join methods check and wait on.
+//!
+//! A [`TryJoinableHandle`] can also be `.await`ed from async code, see [`JoinFuture`].
+//! On Linux, its `completion_fd` is an eventfd becoming readable when the thread finished,
+//! to wait for it with `epoll` next to other file descriptors.
+//! With the `mio` feature the handle is a `mio` event source,
+//! with the `polling` feature it can be added to a `polling::Poller`.
+//! The `tokio` feature adds `tokio_join`, awaiting the thread through Tokio's reactor.
+//! `into_receiver` turns it into a channel receiving the thread's result,
+//! also as a `crossbeam_channel::Receiver` with the `crossbeam-channel` feature.
+//!
+//! Threads created by C code can be try-joined through `RawPthread` on Linux,
+//! which also returns the thread's `*mut c_void` exit value.
+//! [`TryJoinableHandle::try_join_with_info`] reports the thread's name and kernel thread ID
+//! along with the result, which is more useful in logs than a `pthread_t`.
+//! When a thread doesn't finish in time, `Watchdog` reports its kernel thread ID and,
+//! read from `/proc`, what it is blocked on.
 //!
 //! Use an additional `join` to get to the actual underlying result of the thread.
 //!
@@ -19,165 +44,290 @@
 //! ```rust
 //! # use std::time::Duration;
 //! # use std::thread;
-//! # #[cfg(not(target_os = "linux"))]
-//! # fn main() {}
-//! # #[cfg(target_os = "linux")]
-//! # fn main() {
 //! use thread_tryjoin::TryJoinHandle;
 //!
 //! let t = thread::spawn(|| { thread::sleep(Duration::from_secs(1)); });
 //! assert!(t.try_join().is_err());
-//! # }
 //! ```
 //!
 //! To perform a join-with-timeout there is a `try_timed_join` method.
+//! `try_join_until` does the same with an absolute `Instant` as the deadline.
 //!
 //! # Example join-with-timeout
 //!
 //! ```rust
 //! # use std::time::Duration;
 //! # use std::thread;
-//! # #[cfg(not(target_os = "linux"))]
-//! # fn main() {}
-//! # #[cfg(target_os = "linux")]
-//! # fn main() {
 //! use thread_tryjoin::TryJoinHandle;
 //!
 //! let t = thread::spawn(|| {
 //!     thread::sleep(Duration::from_millis(200));
 //! });
 //! assert!(t.try_timed_join(Duration::from_millis(500)).is_ok());
-//! # }
 //! ```
+//!
+//! If you want the thread's value as soon as it is available, `try_join_into` consumes the
+//! handle and either returns the result of the thread or gives the handle back.
+//!
+//! # Example consuming try-join
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use std::thread;
+//! use thread_tryjoin::TryJoinHandle;
+//!
+//! let mut t = thread::spawn(|| 42);
+//! let value = loop {
+//!     match t.try_join_into() {
+//!         Ok(result) => break result.unwrap(),
+//!         Err(handle) => t = handle,
+//!     }
+//!     thread::sleep(Duration::from_millis(10));
+//! };
+//! assert_eq!(42, value);
+//! ```
+//!
+//! [`poll_join`] writes this loop for you, with a configurable [`Backoff`] and deadline.
 #![deny(missing_docs)]
 
 extern crate libc;
 
-use std::io::Error as IoError;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod backoff;
+mod cancel;
+mod channel;
+mod completion;
+mod error;
 #[cfg(target_os = "linux")]
-use std::os::unix::thread::JoinHandleExt;
+mod eventfd;
+mod finished;
+mod future;
+mod handle;
+mod info;
+mod join_all;
 #[cfg(target_os = "linux")]
-use std::ptr;
-use std::thread;
-use std::time::Duration;
+mod linux;
+mod poll;
+#[cfg(not(all(target_os = "linux", not(feature = "portable"))))]
+mod portable;
+mod set;
+mod shutdown;
+mod supervisor;
+#[cfg(all(target_os = "linux", feature = "tokio"))]
+mod tokio_join;
 #[cfg(target_os = "linux")]
-use std::time::{self, SystemTime};
+mod watchdog;
 
+pub use backoff::Backoff;
+pub use cancel::{spawn_cancellable, CancellableHandle, CancellationToken};
+pub use error::TryJoinError;
 #[cfg(target_os = "linux")]
-extern "C" {
-    fn pthread_tryjoin_np(thread: libc::pthread_t, retval: *mut *mut libc::c_void) -> libc::c_int;
-    fn pthread_timedjoin_np(
-        thread: libc::pthread_t,
-        retval: *mut *mut libc::c_void,
-        abstime: *const libc::timespec,
-    ) -> libc::c_int;
-}
+pub use eventfd::CompletionFd;
+pub use finished::Finished;
+pub use future::JoinFuture;
+pub use handle::{spawn, Builder, TryJoinableHandle};
+pub use info::{ThreadError, ThreadInfo};
+pub use join_all::{join_all_timeout, JoinAll};
+#[cfg(target_os = "linux")]
+pub use linux::RawPthread;
+pub use poll::poll_join;
+pub use set::TryJoinSet;
+pub use shutdown::{ShutdownCoordinator, ShutdownReport, ThreadExit, ThreadReport};
+pub use supervisor::{Strategy, Supervisor, SupervisorError, WorkerContext};
+#[cfg(all(target_os = "linux", feature = "tokio"))]
+pub use tokio_join::{tokio_join, tokio_join_timeout, tokio_join_until};
+#[cfg(target_os = "linux")]
+pub use watchdog::{HangReport, Hung, Watchdog};
 
 /// Try joining a thread.
 pub trait TryJoinHandle {
+    /// The value the thread returns.
+    type Output;
+
     /// Try joining a thread.
-    fn try_join(&self) -> Result<(), IoError>;
+    fn try_join(&self) -> Result<(), TryJoinError>;
 
     /// Try joining a thread with a timeout.
     ///
     /// This waits for the specified duration.
     /// If the timeout expires before the thread terminates, the call returns an error.
     /// Otherwise it succeeds.
-    fn try_timed_join(&self, wait: Duration) -> Result<(), IoError>;
+    fn try_timed_join(&self, wait: Duration) -> Result<(), TryJoinError>;
+
+    /// Try joining a thread until a deadline.
+    ///
+    /// This waits until `deadline` at most.
+    /// If the deadline passes before the thread terminates, the call returns an error.
+    /// Otherwise it succeeds.
+    ///
+    /// As the deadline is absolute, several handles can be waited on with the same deadline
+    /// without recomputing the remaining time in between.
+    fn try_join_until(&self, deadline: Instant) -> Result<(), TryJoinError>;
+
+    /// Check if the thread has finished running its closure.
+    ///
+    /// Unlike `try_join` this never reaps the thread,
+    /// so it can be called any number of times and a later `join` works as usual.
+    /// Once this returns `true` the thread is about to exit, though joining it may
+    /// still block for a brief moment.
+    fn is_finished(&self) -> bool;
+
+    /// Try joining a thread, consuming the handle.
+    ///
+    /// If the thread has finished, its result is returned just like `JoinHandle::join` would.
+    /// Otherwise the handle is given back untouched, so it can be tried again later.
+    fn try_join_into(self) -> Result<thread::Result<Self::Output>, Self>
+    where
+        Self: Sized;
+
+    /// Try joining a thread with a timeout, consuming the handle.
+    ///
+    /// This waits for the specified duration.
+    /// If the timeout expires before the thread terminates, the handle is given back.
+    /// Otherwise the result of the thread is returned.
+    fn try_timed_join_into(self, wait: Duration) -> Result<thread::Result<Self::Output>, Self>
+    where
+        Self: Sized;
+
+    /// Try joining a thread, consuming the handle, and tell whether it panicked.
+    ///
+    /// This is `try_join_into`, with the result turned into a [`Finished`].
+    fn try_join_finished(self) -> Result<Finished<Self::Output>, Self>
+    where
+        Self: Sized,
+    {
+        self.try_join_into().map(Finished::from)
+    }
 }
 
-#[cfg(all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64")))]
-impl<T> TryJoinHandle for thread::JoinHandle<T> {
-    fn try_join(&self) -> Result<(), IoError> {
-        unsafe {
-            let thread = self.as_pthread_t();
-
-            match pthread_tryjoin_np(thread, ptr::null_mut()) {
-                0 => Ok(()),
-                err => Err(IoError::from_raw_os_error(err)),
-            }
-        }
+/// Consume a finished handle, or give it back if the thread is still running.
+///
+/// This only looks at `JoinHandle::is_finished` and never reaps the pthread itself,
+/// so the following `join` is the one and only join of the thread.
+fn join_into<T>(handle: thread::JoinHandle<T>) -> Result<thread::Result<T>, thread::JoinHandle<T>> {
+    if handle.is_finished() {
+        Ok(handle.join())
+    } else {
+        Err(handle)
     }
-    fn try_timed_join(&self, wait: Duration) -> Result<(), IoError> {
-        unsafe {
-            let thread = self.as_pthread_t();
-
-            let now = SystemTime::now();
-            let future = now + wait;
-            let total = future
-                .duration_since(time::UNIX_EPOCH)
-                .expect("Can't get time offset");
-            let abstime = libc::timespec {
-                tv_sec: total.as_secs() as i64,
-                tv_nsec: total.subsec_nanos() as i64,
-            };
+}
+
+/// Wait until `is_finished` returns `true` or `deadline` passed.
+///
+/// There is no way to block on a `JoinHandle` without joining it, so this polls with an
+/// increasing interval, never sleeping past the deadline.
+fn wait_finished(is_finished: impl Fn() -> bool, deadline: Instant) -> bool {
+    let mut interval = Duration::from_millis(1);
 
-            match pthread_timedjoin_np(thread, ptr::null_mut(), &abstime as *const libc::timespec) {
-                0 => Ok(()),
-                err => Err(IoError::from_raw_os_error(err)),
-            }
+    while !is_finished() {
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
         }
+        thread::sleep(interval.min(deadline - now));
+        interval = (interval * 2).min(Duration::from_millis(50));
     }
+
+    true
 }
 
-#[cfg(all(target_os = "linux", any(target_arch = "arm", target_arch = "aarch64")))]
+/// `std::thread::JoinHandle` does its own `pthread_join` on `join` and `pthread_detach` on drop,
+/// so reaping the pthread behind its back is undefined behaviour.
+/// This implementation therefore only observes whether the thread finished.
+/// Use [`spawn`] to get a handle that can be waited on without polling.
 impl<T> TryJoinHandle for thread::JoinHandle<T> {
-    fn try_join(&self) -> Result<(), IoError> {
-        unsafe {
-            let thread = self.as_pthread_t();
-
-            match pthread_tryjoin_np(thread, ptr::null_mut()) {
-                0 => Ok(()),
-                err @ _ => Err(IoError::from_raw_os_error(err)),
-            }
+    type Output = T;
+
+    fn try_join(&self) -> Result<(), TryJoinError> {
+        if self.is_finished() {
+            Ok(())
+        } else {
+            Err(TryJoinError::StillRunning)
         }
     }
-    fn try_timed_join(&self, wait: Duration) -> Result<(), IoError> {
-        unsafe {
-            let thread = self.as_pthread_t();
 
-            let now = SystemTime::now();
-            let future = now + wait;
-            let total = future
-                .duration_since(time::UNIX_EPOCH)
-                .expect("Can't get time offset");
+    fn try_timed_join(&self, wait: Duration) -> Result<(), TryJoinError> {
+        self.try_join_until(Instant::now() + wait)
+    }
 
-            #[cfg(target_arch = "arm")]
-            let abstime = libc::timespec {
-                tv_sec: total.as_secs() as i32,
-                tv_nsec: total.subsec_nanos() as i32,
-            };
+    fn try_join_until(&self, deadline: Instant) -> Result<(), TryJoinError> {
+        if wait_finished(|| self.is_finished(), deadline) {
+            Ok(())
+        } else {
+            Err(TryJoinError::TimedOut)
+        }
+    }
 
-            #[cfg(target_arch = "aarch64")]
-            let abstime = libc::timespec {
-                tv_sec: total.as_secs() as i64,
-                tv_nsec: total.subsec_nanos() as i64,
-            };
+    fn is_finished(&self) -> bool {
+        thread::JoinHandle::is_finished(self)
+    }
 
-            match pthread_timedjoin_np(thread, ptr::null_mut(), &abstime as *const libc::timespec) {
-                0 => Ok(()),
-                err @ _ => Err(IoError::from_raw_os_error(err)),
-            }
+    fn try_join_into(self) -> Result<thread::Result<T>, Self> {
+        join_into(self)
+    }
+
+    fn try_timed_join_into(self, wait: Duration) -> Result<thread::Result<T>, Self> {
+        if wait_finished(|| self.is_finished(), Instant::now() + wait) {
+            join_into(self)
+        } else {
+            Err(self)
         }
     }
 }
 
-#[cfg(not(target_os = "linux"))]
-impl<T> TryJoinHandle for thread::JoinHandle<T> {
-    fn try_join(&self) -> Result<(), IoError> {
-        Err(IoError::from_raw_os_error(2))
+/// Scoped threads are joined by their `ScopedJoinHandle` or at the end of the scope,
+/// so just like for `JoinHandle` this implementation only observes whether the thread finished.
+impl<'scope, T> TryJoinHandle for thread::ScopedJoinHandle<'scope, T> {
+    type Output = T;
+
+    fn try_join(&self) -> Result<(), TryJoinError> {
+        if self.is_finished() {
+            Ok(())
+        } else {
+            Err(TryJoinError::StillRunning)
+        }
+    }
+
+    fn try_timed_join(&self, wait: Duration) -> Result<(), TryJoinError> {
+        self.try_join_until(Instant::now() + wait)
+    }
+
+    fn try_join_until(&self, deadline: Instant) -> Result<(), TryJoinError> {
+        if wait_finished(|| self.is_finished(), deadline) {
+            Ok(())
+        } else {
+            Err(TryJoinError::TimedOut)
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        thread::ScopedJoinHandle::is_finished(self)
     }
 
-    fn try_timed_join(&self, _wait: Duration) -> Result<(), IoError> {
-        Err(IoError::from_raw_os_error(2))
+    fn try_join_into(self) -> Result<thread::Result<T>, Self> {
+        if self.is_finished() {
+            Ok(self.join())
+        } else {
+            Err(self)
+        }
+    }
+
+    fn try_timed_join_into(self, wait: Duration) -> Result<thread::Result<T>, Self> {
+        if wait_finished(|| self.is_finished(), Instant::now() + wait) {
+            self.try_join_into()
+        } else {
+            Err(self)
+        }
     }
 }
 
-#[cfg(all(test, target_os = "linux"))]
+#[cfg(test)]
 mod test {
     use super::*;
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn basic_join() {
@@ -202,8 +352,9 @@ mod test {
         });
 
         let err = t.try_join().unwrap_err();
+        assert!(matches!(err, TryJoinError::StillRunning));
         // 16 is EBUSY
-        assert_eq!(Some(16), err.raw_os_error());
+        assert_eq!(Some(16), std::io::Error::from(err).raw_os_error());
 
         thread::sleep(Duration::from_secs(1));
 
@@ -231,4 +382,145 @@ mod test {
         });
         assert!(t.try_timed_join(Duration::from_millis(500)).is_ok());
     }
+
+    #[test]
+    fn try_join_into_returns_value() {
+        let t = thread::spawn(|| "ok");
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!("ok", t.try_join_into().ok().unwrap().unwrap());
+    }
+
+    #[test]
+    fn try_join_into_gives_handle_back() {
+        let t = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(300));
+            "ok"
+        });
+
+        let t = t.try_join_into().unwrap_err();
+        assert_eq!("ok", t.join().unwrap());
+    }
+
+    #[test]
+    fn try_join_into_panicked() {
+        let t = thread::spawn(|| panic!("boom"));
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(t.try_join_into().ok().unwrap().is_err());
+    }
+
+    #[test]
+    fn timed_join_into_returns_value() {
+        let t = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(100));
+            "ok"
+        });
+
+        let result = t.try_timed_join_into(Duration::from_millis(500));
+        assert_eq!("ok", result.ok().unwrap().unwrap());
+    }
+
+    #[test]
+    fn timed_join_into_timeout() {
+        let t = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(500));
+            "ok"
+        });
+
+        let t = t
+            .try_timed_join_into(Duration::from_millis(100))
+            .unwrap_err();
+        assert_eq!("ok", t.join().unwrap());
+    }
+
+    #[test]
+    fn join_until_deadline() {
+        let deadline = Instant::now() + Duration::from_millis(300);
+        let fast = thread::spawn(|| thread::sleep(Duration::from_millis(100)));
+        let slow = thread::spawn(|| thread::sleep(Duration::from_millis(600)));
+
+        assert!(fast.try_join_until(deadline).is_ok());
+        let err = slow.try_join_until(deadline).unwrap_err();
+        assert!(matches!(err, TryJoinError::TimedOut));
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    fn is_finished_does_not_join() {
+        let t = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(100));
+            "ok"
+        });
+
+        assert!(!TryJoinHandle::is_finished(&t));
+        thread::sleep(Duration::from_millis(300));
+        assert!(TryJoinHandle::is_finished(&t));
+        assert!(TryJoinHandle::is_finished(&t));
+        assert_eq!("ok", t.join().unwrap());
+    }
+
+    #[test]
+    fn scoped_try_join() {
+        let mut data = vec![1, 2, 3];
+
+        thread::scope(|s| {
+            let t = s.spawn(|| {
+                thread::sleep(Duration::from_millis(200));
+                data.push(4);
+                data.len()
+            });
+
+            assert!(matches!(t.try_join(), Err(TryJoinError::StillRunning)));
+            assert!(matches!(
+                t.try_timed_join(Duration::from_millis(50)),
+                Err(TryJoinError::TimedOut)
+            ));
+            assert!(t.try_timed_join(Duration::from_secs(1)).is_ok());
+            assert!(t.try_join().is_ok());
+            assert_eq!(4, t.join().unwrap());
+        });
+
+        assert_eq!(vec![1, 2, 3, 4], data);
+    }
+
+    #[test]
+    fn scoped_try_join_into() {
+        let data = [1, 2, 3];
+
+        thread::scope(|s| {
+            let mut t = s.spawn(|| {
+                thread::sleep(Duration::from_millis(100));
+                data.iter().sum::<i32>()
+            });
+
+            let mut polls = 0;
+            let sum = loop {
+                match t.try_join_into() {
+                    Ok(result) => break result.unwrap(),
+                    Err(handle) => t = handle,
+                }
+                polls += 1;
+                thread::sleep(Duration::from_millis(10));
+            };
+            assert_eq!(6, sum);
+            assert!(polls > 0);
+        });
+    }
+
+    #[test]
+    fn scoped_timed_join_into() {
+        thread::scope(|s| {
+            let t = s.spawn(|| {
+                thread::sleep(Duration::from_millis(300));
+                "ok"
+            });
+
+            let t = t
+                .try_timed_join_into(Duration::from_millis(50))
+                .unwrap_err();
+            let result = t.try_timed_join_into(Duration::from_secs(1));
+            assert_eq!("ok", result.ok().unwrap().unwrap());
+        });
+    }
 }
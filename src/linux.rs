@@ -0,0 +1,512 @@
+//! Access to the non-portable pthread join functions.
+
+use std::mem;
+use std::ptr;
+use std::sync::{Condvar, Mutex, MutexGuard, OnceLock};
+use std::time::{self, Duration, Instant, SystemTime};
+
+use crate::TryJoinError;
+
+extern "C" {
+    fn pthread_tryjoin_np(thread: libc::pthread_t, retval: *mut *mut libc::c_void) -> libc::c_int;
+    fn pthread_timedjoin_np(
+        thread: libc::pthread_t,
+        retval: *mut *mut libc::c_void,
+        abstime: *const libc::timespec,
+    ) -> libc::c_int;
+}
+
+type ClockJoinFn = unsafe extern "C" fn(
+    thread: libc::pthread_t,
+    retval: *mut *mut libc::c_void,
+    clockid: libc::clockid_t,
+    abstime: *const libc::timespec,
+) -> libc::c_int;
+
+/// Look up `pthread_clockjoin_np`.
+///
+/// It is only available since glibc 2.34 (and not at all in musl),
+/// so it is resolved at runtime instead of being linked against.
+fn clockjoin() -> Option<ClockJoinFn> {
+    static CLOCKJOIN: OnceLock<Option<ClockJoinFn>> = OnceLock::new();
+
+    *CLOCKJOIN.get_or_init(|| unsafe {
+        let sym = libc::dlsym(libc::RTLD_DEFAULT, c"pthread_clockjoin_np".as_ptr());
+        if sym.is_null() {
+            None
+        } else {
+            Some(mem::transmute::<*mut libc::c_void, ClockJoinFn>(sym))
+        }
+    })
+}
+
+/// The value a joined thread exited with.
+#[derive(Clone, Copy)]
+struct ExitValue(*mut libc::c_void);
+
+// The pointer is only handed back to the owner of the thread, never dereferenced.
+unsafe impl Send for ExitValue {}
+
+/// Where an owned pthread is at, as far as joining it goes.
+enum State {
+    /// Nobody is joining the thread right now.
+    Running,
+    /// Someone is blocked in one of the join functions, without holding the lock.
+    Joining,
+    /// The thread was reaped and exited with this value.
+    Joined(ExitValue),
+}
+
+/// An owned, joinable pthread.
+///
+/// Keeps track of whether the thread was already reaped,
+/// so it is joined at most once and never detached after being joined.
+/// Only one caller blocks in a join function at a time, the others wait on `cvar`,
+/// so each of them still returns by its own deadline.
+pub(crate) struct Native {
+    thread: libc::pthread_t,
+    state: Mutex<State>,
+    /// Notified whenever a blocking join returned.
+    cvar: Condvar,
+}
+
+// musl defines `pthread_t` as a pointer, but it is only an opaque ID for the thread.
+unsafe impl Send for Native {}
+unsafe impl Sync for Native {}
+
+impl Native {
+    /// Take ownership of a joinable pthread.
+    pub(crate) fn new(thread: libc::pthread_t) -> Native {
+        Native {
+            thread,
+            state: Mutex::new(State::Running),
+            cvar: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record the outcome of one of the join functions.
+    fn joined(
+        state: &mut State,
+        ret: libc::c_int,
+        retval: *mut libc::c_void,
+    ) -> Result<*mut libc::c_void, TryJoinError> {
+        match ret {
+            0 => {
+                *state = State::Joined(ExitValue(retval));
+                Ok(retval)
+            }
+            err => {
+                *state = State::Running;
+                Err(TryJoinError::from_raw_os_error(err))
+            }
+        }
+    }
+
+    pub(crate) fn try_join(&self) -> Result<*mut libc::c_void, TryJoinError> {
+        let mut state = self.lock();
+        match *state {
+            State::Joined(ExitValue(retval)) => return Ok(retval),
+            // Someone else is currently joining the thread.
+            State::Joining => return Err(TryJoinError::StillRunning),
+            State::Running => {}
+        }
+
+        let mut retval = ptr::null_mut();
+        let ret = unsafe { pthread_tryjoin_np(self.thread, &mut retval) };
+        Native::joined(&mut state, ret, retval)
+    }
+
+    /// Join the thread, waiting at most `wait`.
+    ///
+    /// The timeout is measured on `CLOCK_MONOTONIC` if `pthread_clockjoin_np` is available,
+    /// so changes to the system time don't affect it.
+    /// Otherwise this falls back to `pthread_timedjoin_np` and the wall clock.
+    pub(crate) fn timed_join(&self, wait: Duration) -> Result<*mut libc::c_void, TryJoinError> {
+        self.join_until(Instant::now() + wait)
+    }
+
+    /// Join the thread, waiting until `deadline` at most.
+    ///
+    /// `Instant` is based on `CLOCK_MONOTONIC` as well, so the deadline is mapped onto it
+    /// by adding the time remaining until the deadline to the current monotonic time.
+    pub(crate) fn join_until(&self, deadline: Instant) -> Result<*mut libc::c_void, TryJoinError> {
+        self.join_until_with(clockjoin(), SystemTime::now, deadline)
+    }
+
+    /// Like `join_until`, using `clockjoin` if given and the fallback otherwise,
+    /// with `wall_clock` telling the current wall-clock time for the fallback.
+    fn join_until_with(
+        &self,
+        clockjoin: Option<ClockJoinFn>,
+        wall_clock: fn() -> SystemTime,
+        deadline: Instant,
+    ) -> Result<*mut libc::c_void, TryJoinError> {
+        let mut state = self.lock();
+        loop {
+            match *state {
+                State::Joined(ExitValue(retval)) => return Ok(retval),
+                State::Running => break,
+                // Wait for the other caller instead of blocking on the lock for its whole timeout.
+                State::Joining => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(TryJoinError::TimedOut);
+                    }
+                    state = self
+                        .cvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                }
+            }
+        }
+
+        let mut retval = ptr::null_mut();
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let ret = match clockjoin {
+            Some(clockjoin) => {
+                let abstime = timespec(monotonic_now() + remaining);
+                *state = State::Joining;
+                drop(state);
+                unsafe { clockjoin(self.thread, &mut retval, libc::CLOCK_MONOTONIC, &abstime) }
+            }
+            None => {
+                // Read only now, as waiting for another caller above may have taken a while.
+                let total = (wall_clock() + remaining)
+                    .duration_since(time::UNIX_EPOCH)
+                    .expect("Can't get time offset");
+                let abstime = timespec(total);
+                *state = State::Joining;
+                drop(state);
+                unsafe { pthread_timedjoin_np(self.thread, &mut retval, &abstime) }
+            }
+        };
+
+        let mut state = self.lock();
+        let result = Native::joined(&mut state, ret, retval);
+        self.cvar.notify_all();
+        result
+    }
+
+    pub(crate) fn join(self) -> Result<*mut libc::c_void, TryJoinError> {
+        let mut state = self.lock();
+        if let State::Joined(ExitValue(retval)) = *state {
+            return Ok(retval);
+        }
+
+        let mut retval = ptr::null_mut();
+        let ret = unsafe { libc::pthread_join(self.thread, &mut retval) };
+        Native::joined(&mut state, ret, retval)
+    }
+}
+
+impl Drop for Native {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+        if !matches!(state, State::Joined(_)) {
+            unsafe {
+                libc::pthread_detach(self.thread);
+            }
+        }
+    }
+}
+
+/// A joinable pthread that was not created by Rust, for example by a native library.
+///
+/// It can be try-joined like a [`TryJoinableHandle`](crate::TryJoinableHandle),
+/// but returns the `*mut c_void` the thread exited with instead of a Rust value.
+/// Once joined, the exit value is remembered and returned by every further join,
+/// without joining the reaped thread again.
+/// Dropping a `RawPthread` that wasn't joined detaches the thread.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::{mem, ptr};
+/// # use std::time::Duration;
+/// use thread_tryjoin::RawPthread;
+///
+/// extern "C" fn run(arg: *mut libc::c_void) -> *mut libc::c_void {
+///     arg
+/// }
+///
+/// let mut thread: libc::pthread_t = unsafe { mem::zeroed() };
+/// let arg = 42 as *mut libc::c_void;
+/// assert_eq!(0, unsafe { libc::pthread_create(&mut thread, ptr::null(), run, arg) });
+///
+/// let thread = unsafe { RawPthread::from_raw(thread) };
+/// assert_eq!(arg, thread.try_timed_join(Duration::from_secs(1)).unwrap());
+/// assert_eq!(arg, thread.join().unwrap());
+/// ```
+pub struct RawPthread {
+    native: Native,
+}
+
+impl RawPthread {
+    /// Take ownership of a pthread.
+    ///
+    /// # Safety
+    ///
+    /// `thread` has to be a joinable thread,
+    /// which is neither joined nor detached by anyone else afterwards.
+    pub unsafe fn from_raw(thread: libc::pthread_t) -> RawPthread {
+        RawPthread {
+            native: Native::new(thread),
+        }
+    }
+
+    /// The underlying `pthread_t`.
+    pub fn as_raw(&self) -> libc::pthread_t {
+        self.native.thread
+    }
+
+    /// Try joining the thread, returning its exit value.
+    pub fn try_join(&self) -> Result<*mut libc::c_void, TryJoinError> {
+        self.native.try_join()
+    }
+
+    /// Try joining the thread with a timeout, returning its exit value.
+    ///
+    /// This waits for the specified duration.
+    /// If the timeout expires before the thread terminates, the call returns an error.
+    pub fn try_timed_join(&self, wait: Duration) -> Result<*mut libc::c_void, TryJoinError> {
+        self.native.timed_join(wait)
+    }
+
+    /// Try joining the thread until a deadline, returning its exit value.
+    ///
+    /// If the deadline passes before the thread terminates, the call returns an error.
+    pub fn try_join_until(&self, deadline: Instant) -> Result<*mut libc::c_void, TryJoinError> {
+        self.native.join_until(deadline)
+    }
+
+    /// Wait for the thread to finish, returning its exit value.
+    pub fn join(self) -> Result<*mut libc::c_void, TryJoinError> {
+        self.native.join()
+    }
+}
+
+/// The current time of `CLOCK_MONOTONIC`.
+fn monotonic_now() -> Duration {
+    let mut now: libc::timespec = unsafe { mem::zeroed() };
+    let ret = unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    assert_eq!(0, ret, "CLOCK_MONOTONIC is not available");
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
+/// Convert a time since the epoch of some clock into a `timespec`.
+///
+/// The field types differ between targets (`tv_nsec` is `i64` on x32),
+/// and 32-bit musl with 64-bit time has private padding,
+/// so the fields are assigned one by one instead of using a struct literal.
+fn timespec(total: Duration) -> libc::timespec {
+    let mut ts: libc::timespec = unsafe { mem::zeroed() };
+    ts.tv_sec = total.as_secs() as _;
+    ts.tv_nsec = total.subsec_nanos() as _;
+    ts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::thread::JoinHandleExt;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn spawn_sleeping(duration: Duration) -> Native {
+        let handle = thread::spawn(move || thread::sleep(duration));
+        Native::new(handle.into_pthread_t() as libc::pthread_t)
+    }
+
+    #[test]
+    fn timed_join_finishes() {
+        let native = spawn_sleeping(Duration::from_millis(100));
+
+        assert!(native.timed_join(Duration::from_secs(1)).is_ok());
+        assert!(native.join().is_ok());
+    }
+
+    #[test]
+    fn timed_join_times_out() {
+        let native = spawn_sleeping(Duration::from_millis(500));
+
+        let err = native.timed_join(Duration::from_millis(100)).unwrap_err();
+        assert!(matches!(err, TryJoinError::TimedOut));
+        assert!(native.join().is_ok());
+    }
+
+    #[test]
+    fn join_until_past_deadline() {
+        let native = spawn_sleeping(Duration::from_millis(300));
+
+        let err = native.join_until(Instant::now()).unwrap_err();
+        assert!(matches!(err, TryJoinError::TimedOut));
+        assert!(native.join().is_ok());
+    }
+
+    /// The join functions to test: `pthread_clockjoin_np` if available, and the fallback.
+    fn join_fns() -> Vec<Option<ClockJoinFn>> {
+        let mut fns = vec![None];
+        if let Some(clockjoin) = clockjoin() {
+            fns.push(Some(clockjoin));
+        }
+        fns
+    }
+
+    #[test]
+    fn fallback_times_out() {
+        let native = spawn_sleeping(Duration::from_millis(500));
+
+        let start = Instant::now();
+        let err = native
+            .join_until_with(None, SystemTime::now, start + Duration::from_millis(100))
+            .unwrap_err();
+        assert!(matches!(err, TryJoinError::TimedOut));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(native
+            .join_until_with(None, SystemTime::now, start + Duration::from_secs(2))
+            .is_ok());
+    }
+
+    #[test]
+    fn wall_clock_jumped_forward() {
+        for clockjoin in join_fns() {
+            let native = spawn_sleeping(Duration::from_millis(500));
+
+            // The wall clock pretends to be an hour ahead.
+            let start = Instant::now();
+            let ahead = || SystemTime::now() + Duration::from_secs(3600);
+            let result =
+                native.join_until_with(clockjoin, ahead, start + Duration::from_millis(200));
+            if clockjoin.is_some() {
+                // The monotonic timeout is still waited for, and no longer.
+                assert!(matches!(result, Err(TryJoinError::TimedOut)));
+                assert!(start.elapsed() >= Duration::from_millis(200));
+                assert!(start.elapsed() < Duration::from_millis(500));
+            } else {
+                // The wall-clock deadline is an hour away, so the thread finishes first.
+                assert!(result.is_ok());
+                assert!(start.elapsed() >= Duration::from_millis(400));
+            }
+            assert!(native.join().is_ok());
+        }
+    }
+
+    #[test]
+    fn wall_clock_jumped_backward() {
+        for clockjoin in join_fns() {
+            let native = spawn_sleeping(Duration::from_millis(500));
+
+            // The wall clock pretends to be an hour behind.
+            let start = Instant::now();
+            let behind = || SystemTime::now() - Duration::from_secs(3600);
+            let err = native
+                .join_until_with(clockjoin, behind, start + Duration::from_millis(200))
+                .unwrap_err();
+            assert!(matches!(err, TryJoinError::TimedOut));
+            if clockjoin.is_some() {
+                assert!(start.elapsed() >= Duration::from_millis(200));
+            } else {
+                // The wall-clock deadline passed an hour ago.
+                assert!(start.elapsed() < Duration::from_millis(100));
+            }
+            assert!(native.join().is_ok());
+        }
+    }
+
+    #[test]
+    fn concurrent_joins_keep_their_deadlines() {
+        for clockjoin in join_fns() {
+            let join_until = move |native: &Native, deadline| {
+                native.join_until_with(clockjoin, SystemTime::now, deadline)
+            };
+            let native = Arc::new(spawn_sleeping(Duration::from_millis(1000)));
+            let their_native = native.clone();
+            let long = thread::spawn(move || {
+                let deadline = Instant::now() + Duration::from_millis(300);
+                join_until(&their_native, deadline).is_err()
+            });
+            thread::sleep(Duration::from_millis(50));
+
+            let start = Instant::now();
+            let err = join_until(&native, start + Duration::from_millis(50)).unwrap_err();
+            assert!(matches!(err, TryJoinError::TimedOut));
+            assert!(matches!(native.try_join(), Err(TryJoinError::StillRunning)));
+            assert!(start.elapsed() < Duration::from_millis(200));
+
+            // Waiting for the other caller doesn't eat into the own timeout.
+            let err = join_until(&native, start + Duration::from_millis(600)).unwrap_err();
+            assert!(matches!(err, TryJoinError::TimedOut));
+            assert!(start.elapsed() >= Duration::from_millis(600));
+            assert!(long.join().unwrap());
+
+            // Woken up as soon as the other caller reaped the thread.
+            let their_native = native.clone();
+            let long = thread::spawn(move || {
+                let deadline = Instant::now() + Duration::from_secs(2);
+                join_until(&their_native, deadline).is_ok()
+            });
+            thread::sleep(Duration::from_millis(50));
+            assert!(join_until(&native, Instant::now() + Duration::from_secs(2)).is_ok());
+            assert!(start.elapsed() < Duration::from_millis(1500));
+            assert!(long.join().unwrap());
+            assert!(native.try_join().is_ok());
+        }
+    }
+
+    extern "C" fn sleep_and_exit(arg: *mut libc::c_void) -> *mut libc::c_void {
+        thread::sleep(Duration::from_millis(200));
+        arg
+    }
+
+    fn create(arg: usize) -> RawPthread {
+        let mut thread = unsafe { mem::zeroed() };
+        let ret = unsafe {
+            libc::pthread_create(&mut thread, ptr::null(), sleep_and_exit, arg as *mut _)
+        };
+        assert_eq!(0, ret);
+        unsafe { RawPthread::from_raw(thread) }
+    }
+
+    #[test]
+    fn raw_try_join_retval() {
+        let thread = create(42);
+
+        assert!(matches!(thread.try_join(), Err(TryJoinError::StillRunning)));
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(42, thread.try_join().unwrap() as usize);
+        // The exit value is remembered.
+        assert_eq!(42, thread.try_join().unwrap() as usize);
+        assert_eq!(42, thread.join().unwrap() as usize);
+    }
+
+    #[test]
+    fn raw_timed_join_retval() {
+        let thread = create(7);
+
+        let err = thread
+            .try_timed_join(Duration::from_millis(50))
+            .unwrap_err();
+        assert!(matches!(err, TryJoinError::TimedOut));
+        let retval = thread.try_timed_join(Duration::from_secs(1)).unwrap();
+        assert_eq!(7, retval as usize);
+    }
+
+    #[test]
+    fn raw_join_until_retval() {
+        let thread = create(3);
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        assert_eq!(3, thread.try_join_until(deadline).unwrap() as usize);
+    }
+
+    #[test]
+    fn raw_drop_detaches() {
+        let thread = create(0);
+        drop(thread);
+    }
+}
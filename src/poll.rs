@@ -0,0 +1,116 @@
+//! Probing a thread repeatedly while doing other work.
+
+use std::thread;
+use std::time::Instant;
+
+use crate::{Backoff, TryJoinHandle};
+
+/// Try-join the thread again and again until it finished or `deadline` passed.
+///
+/// Between probes `between` is called with the number of failed probes so far,
+/// to do some useful work, then the delay given by `backoff` is slept,
+/// cut short so the thread is probed one last time at the deadline.
+/// If the thread didn't finish by then, the handle is given back.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::{Duration, Instant};
+/// # use std::thread;
+/// use thread_tryjoin::{poll_join, Backoff};
+///
+/// let t = thread::spawn(|| {
+///     thread::sleep(Duration::from_millis(100));
+///     42
+/// });
+///
+/// let backoff = Backoff::Jittered {
+///     initial: Duration::from_millis(1),
+///     max: Duration::from_millis(20),
+/// };
+/// let deadline = Instant::now() + Duration::from_secs(1);
+/// let mut ticks = 0;
+/// let result = poll_join(t, &backoff, deadline, |_| ticks += 1);
+///
+/// assert_eq!(42, result.ok().unwrap().unwrap());
+/// assert!(ticks > 0);
+/// ```
+pub fn poll_join<H, F>(
+    handle: H,
+    backoff: &Backoff,
+    deadline: Instant,
+    mut between: F,
+) -> Result<thread::Result<H::Output>, H>
+where
+    H: TryJoinHandle,
+    F: FnMut(u32),
+{
+    let mut handle = handle;
+    let mut attempt = 0u32;
+    loop {
+        handle = match handle.try_join_into() {
+            Ok(result) => return Ok(result),
+            Err(handle) => handle,
+        };
+        if Instant::now() >= deadline {
+            return Err(handle);
+        }
+
+        between(attempt);
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        thread::sleep(backoff.delay(attempt).min(remaining));
+        attempt = attempt.saturating_add(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spawn;
+    use std::time::Duration;
+
+    #[test]
+    fn finishes_before_deadline() {
+        let t = spawn(|| {
+            thread::sleep(Duration::from_millis(100));
+            "done"
+        });
+
+        let backoff = Backoff::Fixed(Duration::from_millis(10));
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let mut attempts = Vec::new();
+        let result = poll_join(t, &backoff, deadline, |attempt| attempts.push(attempt));
+
+        assert_eq!("done", result.ok().unwrap().unwrap());
+        assert!(attempts.len() >= 5);
+        assert!(attempts.iter().enumerate().all(|(i, a)| i as u32 == *a));
+    }
+
+    #[test]
+    fn deadline_gives_handle_back() {
+        let t = spawn(|| thread::sleep(Duration::from_millis(300)));
+
+        let backoff = Backoff::Exponential {
+            initial: Duration::from_millis(1),
+            max: Duration::from_secs(10),
+        };
+        let start = Instant::now();
+        let t = poll_join(t, &backoff, start + Duration::from_millis(50), |_| {}).unwrap_err();
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50));
+        assert!(elapsed < Duration::from_millis(250));
+        assert!(t.join().is_ok());
+    }
+
+    #[test]
+    fn already_finished_skips_callback() {
+        let t = thread::spawn(|| 1);
+        thread::sleep(Duration::from_millis(50));
+
+        let result = poll_join(t, &Backoff::default(), Instant::now(), |_| {
+            panic!("called between probes")
+        });
+        assert_eq!(1, result.ok().unwrap().unwrap());
+    }
+}
@@ -0,0 +1,95 @@
+//! A try-join implementation that only relies on the standard library.
+//!
+//! Instead of asking the operating system, the spawned thread signals the end of its closure
+//! through a [`Completion`], which can be checked and waited for without joining.
+//! The thread itself is joined by the `std::thread::JoinHandle` once the result is taken.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::completion::Completion;
+use crate::TryJoinError;
+
+/// An owned, joinable thread that signals its completion.
+pub(crate) struct Native {
+    handle: thread::JoinHandle<()>,
+    completion: Arc<Completion>,
+}
+
+impl Native {
+    /// Take ownership of a thread that calls `complete` on `completion` when it is done.
+    pub(crate) fn new(handle: thread::JoinHandle<()>, completion: Arc<Completion>) -> Native {
+        Native { handle, completion }
+    }
+
+    pub(crate) fn try_join(&self) -> Result<(), TryJoinError> {
+        if self.completion.is_complete() {
+            Ok(())
+        } else {
+            Err(TryJoinError::StillRunning)
+        }
+    }
+
+    pub(crate) fn timed_join(&self, wait: Duration) -> Result<(), TryJoinError> {
+        self.join_until(Instant::now() + wait)
+    }
+
+    pub(crate) fn join_until(&self, deadline: Instant) -> Result<(), TryJoinError> {
+        if self.completion.wait_until(deadline) {
+            Ok(())
+        } else {
+            Err(TryJoinError::TimedOut)
+        }
+    }
+
+    pub(crate) fn join(self) -> Result<(), TryJoinError> {
+        // The closure catches panics itself, so there is nothing to report here.
+        let _ = self.handle.join();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spawn_sleeping(duration: Duration) -> Native {
+        let completion = Arc::new(Completion::new());
+        let their_completion = completion.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(duration);
+            their_completion.complete();
+        });
+        Native::new(handle, completion)
+    }
+
+    #[test]
+    fn try_join() {
+        let native = spawn_sleeping(Duration::from_millis(200));
+
+        assert!(matches!(native.try_join(), Err(TryJoinError::StillRunning)));
+        thread::sleep(Duration::from_millis(400));
+        assert!(native.try_join().is_ok());
+        assert!(native.join().is_ok());
+    }
+
+    #[test]
+    fn timed_join_finishes() {
+        let native = spawn_sleeping(Duration::from_millis(100));
+
+        assert!(native.timed_join(Duration::from_secs(1)).is_ok());
+        assert!(native.join().is_ok());
+    }
+
+    #[test]
+    fn timed_join_times_out() {
+        let native = spawn_sleeping(Duration::from_millis(500));
+
+        let start = Instant::now();
+        let err = native.timed_join(Duration::from_millis(100)).unwrap_err();
+        assert!(matches!(err, TryJoinError::TimedOut));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(native.join().is_ok());
+    }
+}
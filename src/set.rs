@@ -0,0 +1,235 @@
+//! Waiting for whichever of many threads finishes first.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use crate::{spawn, TryJoinableHandle};
+
+/// The ids of finished threads, in the order they finished.
+struct Ready {
+    queue: Mutex<VecDeque<ThreadId>>,
+    cvar: Condvar,
+}
+
+impl Ready {
+    fn lock(&self) -> MutexGuard<'_, VecDeque<ThreadId>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, id: ThreadId) {
+        self.lock().push_back(id);
+        self.cvar.notify_one();
+    }
+
+    /// Take the next finished thread, waiting until `deadline` at most.
+    fn pop_until(&self, deadline: Option<Instant>) -> Option<ThreadId> {
+        let mut queue = self.lock();
+        loop {
+            if let Some(id) = queue.pop_front() {
+                return Some(id);
+            }
+            queue = match deadline {
+                None => self.cvar.wait(queue).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    self.cvar
+                        .wait_timeout(queue, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+            };
+        }
+    }
+}
+
+/// A collection of threads, joined in the order they finish.
+///
+/// Every thread notifies the set when it is done,
+/// so finding the next finished thread doesn't need to poll each of them.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use std::thread;
+/// use thread_tryjoin::TryJoinSet;
+///
+/// let mut set = TryJoinSet::new();
+/// set.spawn(|| {
+///     thread::sleep(Duration::from_millis(200));
+///     "slow"
+/// });
+/// set.spawn(|| "fast");
+///
+/// let (_id, result) = set.join_next().unwrap();
+/// assert_eq!("fast", result.unwrap());
+/// let (_id, result) = set.join_next().unwrap();
+/// assert_eq!("slow", result.unwrap());
+/// assert!(set.join_next().is_none());
+/// ```
+pub struct TryJoinSet<T> {
+    handles: HashMap<ThreadId, TryJoinableHandle<T>>,
+    ready: Arc<Ready>,
+}
+
+impl<T> TryJoinSet<T> {
+    /// Create an empty set.
+    pub fn new() -> TryJoinSet<T> {
+        TryJoinSet {
+            handles: HashMap::new(),
+            ready: Arc::new(Ready {
+                queue: Mutex::new(VecDeque::new()),
+                cvar: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Spawn a new thread and add it to the set.
+    ///
+    /// Returns the id of the new thread.
+    pub fn spawn<F>(&mut self, f: F) -> ThreadId
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.insert(spawn(f))
+    }
+
+    /// Add an already spawned thread to the set.
+    ///
+    /// Returns the id of the thread.
+    pub fn insert(&mut self, handle: TryJoinableHandle<T>) -> ThreadId {
+        let id = handle.thread().id();
+        let ready = self.ready.clone();
+        handle.completion().on_complete(move || ready.push(id));
+        self.handles.insert(id, handle);
+        id
+    }
+
+    /// The number of threads in the set, finished or not.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Whether there are no threads in the set.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    /// Join the next finished thread, if there is one.
+    ///
+    /// Returns `None` if no thread has finished yet.
+    pub fn try_join_next(&mut self) -> Option<(ThreadId, thread::Result<T>)> {
+        self.join_next_until(Some(Instant::now()))
+    }
+
+    /// Join the next thread to finish, waiting for the specified duration at most.
+    ///
+    /// Returns `None` if the set is empty or the timeout expires before any thread finishes.
+    pub fn join_next_timeout(&mut self, wait: Duration) -> Option<(ThreadId, thread::Result<T>)> {
+        self.join_next_until(Some(Instant::now() + wait))
+    }
+
+    /// Join the next thread to finish, waiting as long as it takes.
+    ///
+    /// Returns `None` if the set is empty.
+    pub fn join_next(&mut self) -> Option<(ThreadId, thread::Result<T>)> {
+        self.join_next_until(None)
+    }
+
+    fn join_next_until(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Option<(ThreadId, thread::Result<T>)> {
+        if self.handles.is_empty() {
+            return None;
+        }
+
+        let id = self.ready.pop_until(deadline)?;
+        let handle = self
+            .handles
+            .remove(&id)
+            .expect("finished thread is not in the set");
+        Some((id, handle.join()))
+    }
+}
+
+impl<T> Default for TryJoinSet<T> {
+    fn default() -> TryJoinSet<T> {
+        TryJoinSet::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn join_in_finishing_order() {
+        let mut set = TryJoinSet::new();
+        let mut ids = Vec::new();
+        for i in (0..4u64).rev() {
+            ids.push(set.spawn(move || {
+                thread::sleep(Duration::from_millis(100 * i));
+                i
+            }));
+        }
+        assert_eq!(4, set.len());
+
+        let mut values = Vec::new();
+        while let Some((id, result)) = set.join_next() {
+            let value = result.unwrap();
+            assert_eq!(ids[3 - value as usize], id);
+            values.push(value);
+        }
+        assert_eq!(vec![0, 1, 2, 3], values);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn try_join_next() {
+        let mut set = TryJoinSet::new();
+        set.spawn(|| {
+            thread::sleep(Duration::from_millis(200));
+            "ok"
+        });
+
+        assert!(set.try_join_next().is_none());
+        thread::sleep(Duration::from_millis(400));
+        let (_, result) = set.try_join_next().unwrap();
+        assert_eq!("ok", result.unwrap());
+        assert!(set.try_join_next().is_none());
+    }
+
+    #[test]
+    fn join_next_timeout() {
+        let mut set = TryJoinSet::new();
+        set.spawn(|| thread::sleep(Duration::from_millis(300)));
+
+        let start = Instant::now();
+        assert!(set.join_next_timeout(Duration::from_millis(100)).is_none());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(1, set.len());
+
+        assert!(set.join_next_timeout(Duration::from_secs(1)).is_some());
+        assert!(set.join_next_timeout(Duration::from_secs(1)).is_none());
+    }
+
+    #[test]
+    fn insert_finished_thread() {
+        let t = spawn(|| panic!("boom"));
+        thread::sleep(Duration::from_millis(100));
+        let id = t.thread().id();
+
+        let mut set = TryJoinSet::new();
+        assert_eq!(id, set.insert(t));
+        let (joined, result) = set.try_join_next().unwrap();
+        assert_eq!(id, joined);
+        assert!(result.is_err());
+    }
+}
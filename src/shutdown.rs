@@ -0,0 +1,356 @@
+//! Shutting down many threads in phases.
+
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::{CancellableHandle, Finished, TryJoinHandle};
+
+/// How a thread ended during shutdown.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ThreadExit {
+    /// The thread returned.
+    Exited,
+    /// The thread panicked, with its panic message if there is one.
+    Panicked(Option<String>),
+    /// The thread was still running when its phase ended, even after escalation.
+    Hung,
+}
+
+/// What happened to one thread during shutdown.
+#[derive(Clone, Debug)]
+pub struct ThreadReport {
+    /// The name the thread was registered with.
+    pub name: String,
+    /// The phase the thread was stopped in.
+    pub phase: u32,
+    /// How the thread ended.
+    pub exit: ThreadExit,
+    /// Whether the thread missed its grace period and was escalated.
+    pub escalated: bool,
+    /// How long it took from signalling the thread until it was joined or given up on.
+    pub elapsed: Duration,
+}
+
+/// What happened during [`ShutdownCoordinator::shutdown`], in the order threads were stopped.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownReport {
+    /// One report per registered thread.
+    pub threads: Vec<ThreadReport>,
+}
+
+impl ShutdownReport {
+    /// Whether every thread returned without panicking.
+    pub fn is_clean(&self) -> bool {
+        self.threads.iter().all(|t| t.exit == ThreadExit::Exited)
+    }
+
+    /// The threads that didn't exit in time.
+    pub fn hung(&self) -> impl Iterator<Item = &ThreadReport> {
+        self.threads.iter().filter(|t| t.exit == ThreadExit::Hung)
+    }
+
+    /// The threads that panicked.
+    pub fn panicked(&self) -> impl Iterator<Item = &ThreadReport> {
+        self.threads
+            .iter()
+            .filter(|t| matches!(t.exit, ThreadExit::Panicked(_)))
+    }
+}
+
+/// A registered handle, with the type of its thread's value erased.
+trait Stoppable: Send {
+    fn signal(&mut self);
+
+    /// Wait for the thread until `deadline`, returning how it ended if it did.
+    fn join_until(&mut self, deadline: Instant) -> Option<ThreadExit>;
+}
+
+struct Registered<H> {
+    handle: Option<H>,
+    signal: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl<H> Stoppable for Registered<H>
+where
+    H: TryJoinHandle + Send,
+{
+    fn signal(&mut self) {
+        if let Some(signal) = self.signal.take() {
+            signal();
+        }
+    }
+
+    fn join_until(&mut self, deadline: Instant) -> Option<ThreadExit> {
+        let handle = self.handle.take()?;
+        if handle.try_join_until(deadline).is_err() {
+            self.handle = Some(handle);
+            return None;
+        }
+        match handle.try_join_finished() {
+            Ok(Finished::Returned(_)) => Some(ThreadExit::Exited),
+            Ok(finished) => Some(ThreadExit::Panicked(
+                finished.panic_message().map(String::from),
+            )),
+            Err(handle) => {
+                self.handle = Some(handle);
+                None
+            }
+        }
+    }
+}
+
+struct Entry {
+    name: String,
+    phase: u32,
+    handle: Box<dyn Stoppable>,
+}
+
+type EscalateHook = Box<dyn FnMut(&str) + Send>;
+
+/// Stops registered threads phase by phase and reports how each of them ended.
+///
+/// Phases run in ascending order. In each phase all of its threads are signalled first,
+/// then they get a shared grace period to exit.
+/// Threads still running afterwards are escalated:
+/// the escalation hook is called for each of them and they get another shared timeout.
+/// Threads that didn't exit by then are reported as hung and detached.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// use thread_tryjoin::{spawn_cancellable, ShutdownCoordinator};
+///
+/// let mut coordinator = ShutdownCoordinator::new(Duration::from_secs(1));
+///
+/// for phase in 0..2 {
+///     let worker = spawn_cancellable(|token| while !token.wait_timeout(Duration::from_secs(1)) {});
+///     coordinator.register_cancellable(&format!("worker-{}", phase), phase, worker);
+/// }
+///
+/// let report = coordinator.shutdown();
+/// assert!(report.is_clean());
+/// ```
+pub struct ShutdownCoordinator {
+    grace_period: Duration,
+    phase_grace_periods: HashMap<u32, Duration>,
+    escalation: Option<(Duration, EscalateHook)>,
+    entries: Vec<Entry>,
+}
+
+impl ShutdownCoordinator {
+    /// Create a coordinator giving the threads of every phase `grace_period` to exit.
+    pub fn new(grace_period: Duration) -> ShutdownCoordinator {
+        ShutdownCoordinator {
+            grace_period,
+            phase_grace_periods: HashMap::new(),
+            escalation: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Give the threads of `phase` a different grace period.
+    pub fn phase_grace_period(mut self, phase: u32, grace_period: Duration) -> ShutdownCoordinator {
+        self.phase_grace_periods.insert(phase, grace_period);
+        self
+    }
+
+    /// Call `hook` with the name of every thread missing its grace period,
+    /// then wait `timeout` more for those threads.
+    pub fn on_escalate<F>(mut self, timeout: Duration, hook: F) -> ShutdownCoordinator
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        self.escalation = Some((timeout, Box::new(hook)));
+        self
+    }
+
+    /// Register a thread to be stopped in `phase`, by calling `signal`.
+    pub fn register<H, F>(&mut self, name: &str, phase: u32, handle: H, signal: F)
+    where
+        H: TryJoinHandle + Send + 'static,
+        F: FnOnce() + Send + 'static,
+    {
+        self.entries.push(Entry {
+            name: name.to_string(),
+            phase,
+            handle: Box::new(Registered {
+                handle: Some(handle),
+                signal: Some(Box::new(signal)),
+            }),
+        });
+    }
+
+    /// Register a thread to be stopped in `phase`, by cancelling its token.
+    pub fn register_cancellable<T>(&mut self, name: &str, phase: u32, handle: CancellableHandle<T>)
+    where
+        T: Send + 'static,
+    {
+        let token = handle.token().clone();
+        self.register(name, phase, handle, move || token.cancel());
+    }
+
+    /// Stop all registered threads, phase by phase.
+    pub fn shutdown(mut self) -> ShutdownReport {
+        let phases: BTreeSet<u32> = self.entries.iter().map(|e| e.phase).collect();
+        let mut report = ShutdownReport::default();
+
+        for phase in phases {
+            let grace_period = *self
+                .phase_grace_periods
+                .get(&phase)
+                .unwrap_or(&self.grace_period);
+            let (mut entries, rest): (Vec<_>, Vec<_>) =
+                self.entries.into_iter().partition(|e| e.phase == phase);
+            self.entries = rest;
+
+            let start = Instant::now();
+            for entry in &mut entries {
+                entry.handle.signal();
+            }
+
+            let deadline = start + grace_period;
+            let mut stragglers = Vec::new();
+            for mut entry in entries {
+                match entry.handle.join_until(deadline) {
+                    Some(exit) => report
+                        .threads
+                        .push(thread_report(entry, exit, false, start)),
+                    None => stragglers.push(entry),
+                }
+            }
+            if stragglers.is_empty() {
+                continue;
+            }
+
+            let escalation_timeout = match &mut self.escalation {
+                Some((timeout, hook)) => {
+                    for entry in &stragglers {
+                        hook(&entry.name);
+                    }
+                    *timeout
+                }
+                None => Duration::ZERO,
+            };
+            let deadline = Instant::now() + escalation_timeout;
+            for mut entry in stragglers {
+                let exit = entry
+                    .handle
+                    .join_until(deadline)
+                    .unwrap_or(ThreadExit::Hung);
+                let escalated = self.escalation.is_some();
+                report
+                    .threads
+                    .push(thread_report(entry, exit, escalated, start));
+            }
+        }
+
+        report
+    }
+}
+
+fn thread_report(entry: Entry, exit: ThreadExit, escalated: bool, start: Instant) -> ThreadReport {
+    ThreadReport {
+        name: entry.name,
+        phase: entry.phase,
+        exit,
+        escalated,
+        elapsed: start.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{spawn, spawn_cancellable};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    fn cooperative() -> CancellableHandle<()> {
+        spawn_cancellable(|token| while !token.wait_timeout(Duration::from_secs(10)) {})
+    }
+
+    #[test]
+    fn phases_run_in_order() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut coordinator = ShutdownCoordinator::new(Duration::from_secs(1));
+
+        for (name, phase) in [("late", 2), ("early", 0), ("middle", 1)] {
+            let order = order.clone();
+            let worker = spawn_cancellable(move |token| {
+                while !token.wait_timeout(Duration::from_secs(10)) {}
+                order.lock().unwrap().push(name);
+            });
+            coordinator.register_cancellable(name, phase, worker);
+        }
+
+        let report = coordinator.shutdown();
+        assert!(report.is_clean());
+        assert_eq!(vec!["early", "middle", "late"], *order.lock().unwrap());
+        let names: Vec<_> = report.threads.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(vec!["early", "middle", "late"], names);
+    }
+
+    #[test]
+    fn panicked_and_hung_threads() {
+        let escalated = Arc::new(Mutex::new(Vec::new()));
+        let hook_escalated = escalated.clone();
+        let mut coordinator = ShutdownCoordinator::new(Duration::from_millis(100))
+            .on_escalate(Duration::from_millis(100), move |name| {
+                hook_escalated.lock().unwrap().push(name.to_string())
+            });
+
+        coordinator.register_cancellable("fine", 0, cooperative());
+        coordinator.register_cancellable(
+            "crashing",
+            0,
+            spawn_cancellable(|token| {
+                token.wait_timeout(Duration::from_secs(10));
+                panic!("crashed on shutdown");
+            }),
+        );
+        let stuck = spawn(|| thread::sleep(Duration::from_secs(1)));
+        coordinator.register("stuck", 0, stuck, || {});
+
+        let report = coordinator.shutdown();
+        assert!(!report.is_clean());
+        assert_eq!(vec!["stuck".to_string()], *escalated.lock().unwrap());
+
+        let hung: Vec<_> = report.hung().map(|t| t.name.as_str()).collect();
+        assert_eq!(vec!["stuck"], hung);
+        let stuck = report.hung().next().unwrap();
+        assert!(stuck.escalated);
+        assert!(stuck.elapsed >= Duration::from_millis(200));
+
+        let panicked: Vec<_> = report.panicked().map(|t| &t.exit).collect();
+        assert_eq!(
+            vec![&ThreadExit::Panicked(Some(
+                "crashed on shutdown".to_string()
+            ))],
+            panicked
+        );
+    }
+
+    #[test]
+    fn escalation_rescues_straggler() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let their_stop = stop.clone();
+        let stubborn = spawn(move || {
+            while !their_stop.load(Ordering::Acquire) {
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+
+        let mut coordinator = ShutdownCoordinator::new(Duration::from_millis(50))
+            .phase_grace_period(1, Duration::from_millis(10))
+            .on_escalate(Duration::from_secs(1), move |_| {
+                stop.store(true, Ordering::Release)
+            });
+        coordinator.register("stubborn", 1, stubborn, || {});
+
+        let report = coordinator.shutdown();
+        assert_eq!(ThreadExit::Exited, report.threads[0].exit);
+        assert!(report.threads[0].escalated);
+    }
+}
@@ -0,0 +1,593 @@
+//! Restarting crashed worker threads.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{spawn, Backoff, Finished, TryJoinHandle, TryJoinableHandle};
+
+/// Which workers are restarted when one of them crashes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Only the crashed worker is restarted.
+    OneForOne,
+    /// All running workers are asked to stop, and restarted together with the crashed one
+    /// once they have exited and the backoff of every crashed worker has passed.
+    OneForAll,
+}
+
+/// What a supervised worker gets to know about its supervision.
+pub struct WorkerContext {
+    name: Arc<str>,
+    stop: Arc<AtomicBool>,
+}
+
+impl WorkerContext {
+    /// The name the worker was added with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the supervisor asked the worker to stop.
+    ///
+    /// Workers should check this regularly and return once it is set.
+    pub fn is_stopping(&self) -> bool {
+        self.stop.load(Ordering::Acquire)
+    }
+}
+
+/// The error returned when supervision gives up.
+#[derive(Debug)]
+pub enum SupervisorError {
+    /// A worker crashed more often than allowed within the restart window.
+    RestartLimitExceeded {
+        /// The name of the worker.
+        worker: String,
+    },
+}
+
+impl fmt::Display for SupervisorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SupervisorError::RestartLimitExceeded { worker } => {
+                write!(f, "worker {} crashed too often", worker)
+            }
+        }
+    }
+}
+
+impl Error for SupervisorError {}
+
+type WorkerFn = Arc<dyn Fn(&WorkerContext) + Send + Sync + 'static>;
+type PanicHook = Box<dyn FnMut(&str, Option<&str>) + Send + 'static>;
+
+enum State {
+    Running(TryJoinableHandle<()>),
+    /// Asked to stop, to be restarted with the others.
+    Stopping(TryJoinableHandle<()>),
+    /// Waiting to be restarted.
+    Restarting(Instant),
+    /// Returned normally.
+    Done,
+    /// Crashed more often than allowed, not restarted anymore.
+    Failed,
+}
+
+struct Worker {
+    name: Arc<str>,
+    f: WorkerFn,
+    stop: Arc<AtomicBool>,
+    state: State,
+    /// When the worker crashed recently, oldest first.
+    crashes: VecDeque<Instant>,
+}
+
+impl Worker {
+    fn start(&mut self) {
+        self.stop = Arc::new(AtomicBool::new(false));
+        let ctx = WorkerContext {
+            name: self.name.clone(),
+            stop: self.stop.clone(),
+        };
+        let f = self.f.clone();
+        self.state = State::Running(spawn(move || f(&ctx)));
+    }
+}
+
+/// Runs named worker threads and restarts them when they crash.
+///
+/// Workers are polled with [`TryJoinHandle::try_join_finished`] on every call to [`poll`].
+/// A worker returning normally is done and not restarted.
+/// A worker panicking is restarted according to the [`Strategy`],
+/// after a delay given by the [`Backoff`] for the number of its recent crashes.
+/// If a worker crashes more than the allowed number of times within the restart window,
+/// supervision fails.
+///
+/// [`poll`]: Supervisor::poll
+///
+/// # Example
+///
+/// ```rust
+/// # use std::sync::atomic::{AtomicUsize, Ordering};
+/// # use std::sync::Arc;
+/// # use std::time::Duration;
+/// use thread_tryjoin::{Strategy, Supervisor};
+///
+/// let runs = Arc::new(AtomicUsize::new(0));
+/// let mut supervisor = Supervisor::new(Strategy::OneForOne)
+///     .max_restarts(3, Duration::from_secs(10))
+///     .on_panic(|name, msg| eprintln!("worker {} crashed: {:?}", name, msg));
+///
+/// let worker_runs = runs.clone();
+/// supervisor.add_worker("flaky", move |_ctx| {
+///     if worker_runs.fetch_add(1, Ordering::SeqCst) < 2 {
+///         panic!("not yet");
+///     }
+/// });
+///
+/// supervisor.run(Duration::from_millis(10)).unwrap();
+/// assert_eq!(3, runs.load(Ordering::SeqCst));
+/// ```
+pub struct Supervisor {
+    strategy: Strategy,
+    max_restarts: usize,
+    window: Duration,
+    backoff: Backoff,
+    on_panic: Option<PanicHook>,
+    workers: Vec<Worker>,
+}
+
+impl Supervisor {
+    /// Create a supervisor without workers.
+    ///
+    /// By default a worker may be restarted 3 times within 5 seconds, without any delay.
+    pub fn new(strategy: Strategy) -> Supervisor {
+        Supervisor {
+            strategy,
+            max_restarts: 3,
+            window: Duration::from_secs(5),
+            backoff: Backoff::default(),
+            on_panic: None,
+            workers: Vec::new(),
+        }
+    }
+
+    /// Allow a worker to be restarted `max_restarts` times within `window`.
+    pub fn max_restarts(mut self, max_restarts: usize, window: Duration) -> Supervisor {
+        self.max_restarts = max_restarts;
+        self.window = window;
+        self
+    }
+
+    /// Wait before restarting a crashed worker.
+    ///
+    /// The attempt passed to the backoff is the number of times the worker crashed before
+    /// within the restart window.
+    pub fn backoff(mut self, backoff: Backoff) -> Supervisor {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Call `hook` with the worker's name and panic message whenever a worker panics.
+    pub fn on_panic<F>(mut self, hook: F) -> Supervisor
+    where
+        F: FnMut(&str, Option<&str>) + Send + 'static,
+    {
+        self.on_panic = Some(Box::new(hook));
+        self
+    }
+
+    /// Start a new worker.
+    pub fn add_worker<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&WorkerContext) + Send + Sync + 'static,
+    {
+        let mut worker = Worker {
+            name: name.into(),
+            f: Arc::new(f),
+            stop: Arc::new(AtomicBool::new(false)),
+            state: State::Done,
+            crashes: VecDeque::new(),
+        };
+        worker.start();
+        self.workers.push(worker);
+    }
+
+    /// Whether all workers returned and won't be restarted.
+    ///
+    /// Workers that exceeded the restart limit didn't return, so this stays false
+    /// once one of them failed.
+    pub fn is_done(&self) -> bool {
+        self.workers
+            .iter()
+            .all(|worker| matches!(worker.state, State::Done))
+    }
+
+    /// The names of the workers that exceeded the restart limit and won't be restarted.
+    pub fn failed(&self) -> impl Iterator<Item = &str> {
+        self.workers
+            .iter()
+            .filter(|worker| matches!(worker.state, State::Failed))
+            .map(|worker| &*worker.name)
+    }
+
+    /// Check on all workers once, restarting those that crashed.
+    ///
+    /// This never blocks.
+    pub fn poll(&mut self) -> Result<(), SupervisorError> {
+        let now = Instant::now();
+        let mut crashed = Vec::new();
+
+        for (idx, worker) in self.workers.iter_mut().enumerate() {
+            let (handle, stopping) = match mem::replace(&mut worker.state, State::Done) {
+                State::Running(handle) => (handle, false),
+                State::Stopping(handle) => (handle, true),
+                state => {
+                    worker.state = state;
+                    continue;
+                }
+            };
+
+            match handle.try_join_finished() {
+                Err(handle) if stopping => worker.state = State::Stopping(handle),
+                Err(handle) => worker.state = State::Running(handle),
+                Ok(finished) => {
+                    if let (Some(hook), Finished::Panicked(_)) = (&mut self.on_panic, &finished) {
+                        hook(&worker.name, finished.panic_message());
+                    }
+                    // A panic while stopping still counts towards the restart limit.
+                    if finished.is_panicked() {
+                        crashed.push(idx);
+                    } else if stopping {
+                        worker.state = State::Restarting(now);
+                    }
+                }
+            }
+        }
+
+        // Every crashed worker is dealt with before reporting the first one over the limit,
+        // so none of them is left looking like it returned.
+        let mut error = None;
+        for idx in crashed {
+            let worker = &mut self.workers[idx];
+            while let Some(&crash) = worker.crashes.front() {
+                if now.duration_since(crash) < self.window {
+                    break;
+                }
+                worker.crashes.pop_front();
+            }
+            if worker.crashes.len() >= self.max_restarts {
+                worker.state = State::Failed;
+                error.get_or_insert_with(|| SupervisorError::RestartLimitExceeded {
+                    worker: worker.name.to_string(),
+                });
+            } else {
+                let delay = self.backoff.delay(worker.crashes.len() as u32);
+                worker.crashes.push_back(now);
+                worker.state = State::Restarting(now + delay);
+            }
+
+            if self.strategy == Strategy::OneForAll {
+                for other in &mut self.workers {
+                    other.state = match mem::replace(&mut other.state, State::Done) {
+                        State::Running(handle) => {
+                            other.stop.store(true, Ordering::Release);
+                            State::Stopping(handle)
+                        }
+                        state => state,
+                    };
+                }
+            }
+        }
+
+        // With one-for-all, nothing is restarted while some workers are still stopping.
+        let stopping = self
+            .workers
+            .iter()
+            .any(|worker| matches!(worker.state, State::Stopping(_)));
+        if !stopping {
+            // With one-for-all, the whole group restarts at the latest restart time.
+            let group_at = self
+                .workers
+                .iter()
+                .filter_map(|worker| match worker.state {
+                    State::Restarting(at) => Some(at),
+                    _ => None,
+                })
+                .max();
+            for worker in &mut self.workers {
+                if let State::Restarting(at) = worker.state {
+                    let at = match (self.strategy, group_at) {
+                        (Strategy::OneForAll, Some(group_at)) => group_at,
+                        _ => at,
+                    };
+                    if at <= now {
+                        worker.start();
+                    }
+                }
+            }
+        }
+
+        match error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Supervise the workers until all of them are done, polling every `interval`.
+    pub fn run(&mut self, interval: Duration) -> Result<(), SupervisorError> {
+        loop {
+            self.poll()?;
+            if self.is_done() {
+                return Ok(());
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    /// Ask all workers to stop and wait for them to exit.
+    ///
+    /// Workers waiting to be restarted are not started again.
+    pub fn shutdown(self) {
+        for worker in &self.workers {
+            worker.stop.store(true, Ordering::Release);
+        }
+        for worker in self.workers {
+            if let State::Running(handle) | State::Stopping(handle) = worker.state {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Mutex;
+
+    #[test]
+    fn one_for_one_restarts_crashed_worker() {
+        let crashes = Arc::new(Mutex::new(Vec::new()));
+        let runs = Arc::new(AtomicUsize::new(0));
+        let steady_runs = Arc::new(AtomicUsize::new(0));
+
+        let hook_crashes = crashes.clone();
+        let mut supervisor = Supervisor::new(Strategy::OneForOne).on_panic(move |name, msg| {
+            hook_crashes
+                .lock()
+                .unwrap()
+                .push(format!("{}: {}", name, msg.unwrap()))
+        });
+
+        let worker_runs = runs.clone();
+        supervisor.add_worker("flaky", move |_| {
+            let run = worker_runs.fetch_add(1, Ordering::SeqCst);
+            if run < 2 {
+                panic!("run {}", run);
+            }
+        });
+        let worker_runs = steady_runs.clone();
+        supervisor.add_worker("steady", move |ctx| {
+            worker_runs.fetch_add(1, Ordering::SeqCst);
+            while !ctx.is_stopping() {
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while runs.load(Ordering::SeqCst) < 3 && Instant::now() < deadline {
+            supervisor.poll().unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+        supervisor.shutdown();
+
+        assert_eq!(3, runs.load(Ordering::SeqCst));
+        assert_eq!(1, steady_runs.load(Ordering::SeqCst));
+        assert_eq!(
+            vec!["flaky: run 0".to_string(), "flaky: run 1".to_string()],
+            *crashes.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn one_for_all_restarts_everyone() {
+        let crashing_runs = Arc::new(AtomicUsize::new(0));
+        let other_runs = Arc::new(AtomicUsize::new(0));
+
+        let mut supervisor = Supervisor::new(Strategy::OneForAll);
+        let worker_runs = crashing_runs.clone();
+        supervisor.add_worker("crashing", move |_| {
+            if worker_runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                thread::sleep(Duration::from_millis(50));
+                panic!("boom");
+            }
+        });
+        let worker_runs = other_runs.clone();
+        supervisor.add_worker("other", move |ctx| {
+            if worker_runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                while !ctx.is_stopping() {
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+        });
+
+        supervisor.run(Duration::from_millis(10)).unwrap();
+
+        assert_eq!(2, crashing_runs.load(Ordering::SeqCst));
+        assert_eq!(2, other_runs.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn one_for_all_restarts_together_after_backoff() {
+        let starts = Arc::new(Mutex::new(Vec::new()));
+
+        let mut supervisor = Supervisor::new(Strategy::OneForAll)
+            .backoff(Backoff::Fixed(Duration::from_millis(200)));
+        let worker_starts = starts.clone();
+        supervisor.add_worker("crashing", move |_| {
+            let mut starts = worker_starts.lock().unwrap();
+            starts.push(("crashing", Instant::now()));
+            if starts.len() == 1 {
+                drop(starts);
+                panic!("boom");
+            }
+        });
+        let worker_starts = starts.clone();
+        supervisor.add_worker("other", move |ctx| {
+            worker_starts
+                .lock()
+                .unwrap()
+                .push(("other", Instant::now()));
+            while !ctx.is_stopping() {
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+        thread::sleep(Duration::from_millis(50));
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while starts.lock().unwrap().len() < 4 && Instant::now() < deadline {
+            supervisor.poll().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        supervisor.shutdown();
+
+        let starts = starts.lock().unwrap();
+        assert_eq!(4, starts.len());
+        let first = starts[0].1.max(starts[1].1);
+        for (name, at) in &starts[2..] {
+            assert!(
+                *at - first >= Duration::from_millis(200),
+                "{} restarted early",
+                name
+            );
+        }
+        let (a, b) = (starts[2].1, starts[3].1);
+        assert!(a.max(b) - a.min(b) < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn panic_while_stopping_counts_as_crash() {
+        let crashes = Arc::new(Mutex::new(Vec::new()));
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let hook_crashes = crashes.clone();
+        let mut supervisor = Supervisor::new(Strategy::OneForAll)
+            .on_panic(move |name, _| hook_crashes.lock().unwrap().push(name.to_string()));
+        let worker_runs = runs.clone();
+        supervisor.add_worker("crashing", move |_| {
+            if worker_runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                thread::sleep(Duration::from_millis(20));
+                panic!("boom");
+            }
+        });
+        supervisor.add_worker("panics on stop", |ctx| {
+            while !ctx.is_stopping() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            panic!("stopped");
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while runs.load(Ordering::SeqCst) < 2 && Instant::now() < deadline {
+            supervisor.poll().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        assert_eq!(2, runs.load(Ordering::SeqCst));
+        assert_eq!(
+            vec!["crashing".to_string(), "panics on stop".to_string()],
+            *crashes.lock().unwrap()
+        );
+        assert_eq!(1, supervisor.workers[1].crashes.len());
+        supervisor.shutdown();
+    }
+
+    #[test]
+    fn restart_limit() {
+        let mut supervisor =
+            Supervisor::new(Strategy::OneForOne).max_restarts(2, Duration::from_secs(10));
+        supervisor.add_worker("broken", |_| panic!("always"));
+
+        let err = supervisor.run(Duration::from_millis(10)).unwrap_err();
+        assert!(matches!(
+            &err,
+            SupervisorError::RestartLimitExceeded { worker } if worker == "broken"
+        ));
+        assert_eq!("worker broken crashed too often", err.to_string());
+        assert!(!supervisor.is_done());
+        assert_eq!(vec!["broken"], supervisor.failed().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn restart_limit_fails_every_crashed_worker() {
+        let mut supervisor =
+            Supervisor::new(Strategy::OneForAll).max_restarts(0, Duration::from_secs(10));
+        supervisor.add_worker("first", |_| panic!("always"));
+        supervisor.add_worker("second", |_| panic!("always"));
+        supervisor.add_worker("steady", |ctx| {
+            while !ctx.is_stopping() {
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+        thread::sleep(Duration::from_millis(50));
+
+        let err = supervisor.poll().unwrap_err();
+        assert!(matches!(
+            &err,
+            SupervisorError::RestartLimitExceeded { worker } if worker == "first"
+        ));
+        assert_eq!(
+            vec!["first", "second"],
+            supervisor.failed().collect::<Vec<_>>()
+        );
+        assert!(supervisor.workers[2].stop.load(Ordering::Acquire));
+        assert!(!supervisor.is_done());
+        supervisor.shutdown();
+    }
+
+    #[test]
+    fn backoff_delays_restart() {
+        let starts = Arc::new(Mutex::new(Vec::new()));
+
+        let mut supervisor = Supervisor::new(Strategy::OneForOne).backoff(Backoff::Exponential {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+        });
+        let worker_starts = starts.clone();
+        supervisor.add_worker("flaky", move |_| {
+            let mut starts = worker_starts.lock().unwrap();
+            starts.push(Instant::now());
+            if starts.len() < 3 {
+                drop(starts);
+                panic!("not yet");
+            }
+        });
+
+        supervisor.run(Duration::from_millis(5)).unwrap();
+
+        let starts = starts.lock().unwrap();
+        assert_eq!(3, starts.len());
+        assert!(starts[1] - starts[0] >= Duration::from_millis(100));
+        assert!(starts[2] - starts[1] >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn context_name() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = Mutex::new(tx);
+
+        let mut supervisor = Supervisor::new(Strategy::OneForOne);
+        supervisor.add_worker("named", move |ctx| {
+            tx.lock().unwrap().send(ctx.name().to_string()).unwrap()
+        });
+        supervisor.run(Duration::from_millis(10)).unwrap();
+
+        assert_eq!("named", rx.recv().unwrap());
+    }
+}
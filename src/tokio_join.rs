@@ -0,0 +1,177 @@
+//! Awaiting a thread on a Tokio runtime.
+
+use std::os::fd::AsRawFd;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+use crate::{TryJoinHandle, TryJoinableHandle};
+
+/// Wait for the thread to finish and return its result, without blocking the runtime.
+///
+/// The thread's [`CompletionFd`](crate::CompletionFd) is registered with Tokio's reactor,
+/// so neither a blocking-pool thread nor a busy loop is spent waiting.
+///
+/// Must be called from within a Tokio runtime with IO enabled.
+/// Only available on Linux, with the `tokio` feature.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use std::thread;
+/// let rt = tokio::runtime::Builder::new_current_thread()
+///     .enable_all()
+///     .build()
+///     .unwrap();
+///
+/// let t = thread_tryjoin::spawn(|| {
+///     thread::sleep(Duration::from_millis(100));
+///     42
+/// });
+/// let value = rt.block_on(thread_tryjoin::tokio_join(t)).unwrap();
+/// assert_eq!(42, value);
+/// ```
+pub async fn tokio_join<T>(handle: TryJoinableHandle<T>) -> thread::Result<T> {
+    finished(&handle).await;
+    handle.join()
+}
+
+/// Like [`tokio_join`], but waits for the specified duration at most.
+///
+/// If the thread doesn't finish in time, the handle is given back.
+pub async fn tokio_join_timeout<T>(
+    handle: TryJoinableHandle<T>,
+    wait: Duration,
+) -> Result<thread::Result<T>, TryJoinableHandle<T>> {
+    match tokio::time::timeout(wait, finished(&handle)).await {
+        Ok(()) => Ok(handle.join()),
+        Err(_) => Err(handle),
+    }
+}
+
+/// Like [`tokio_join`], but waits until `deadline` at most.
+///
+/// If the thread doesn't finish in time, the handle is given back.
+pub async fn tokio_join_until<T>(
+    handle: TryJoinableHandle<T>,
+    deadline: Instant,
+) -> Result<thread::Result<T>, TryJoinableHandle<T>> {
+    let deadline = tokio::time::Instant::from_std(deadline);
+    match tokio::time::timeout_at(deadline, finished(&handle)).await {
+        Ok(()) => Ok(handle.join()),
+        Err(_) => Err(handle),
+    }
+}
+
+/// Wait until the thread's result is available.
+///
+/// Waiting again after a timeout reuses the handle's descriptor or `Notify`,
+/// so retrying doesn't pile up descriptors or callbacks.
+async fn finished<T>(handle: &TryJoinableHandle<T>) {
+    if handle.is_finished() {
+        return;
+    }
+
+    // The descriptor stays owned by the handle, the reactor only borrows its number.
+    let fd = handle
+        .registered_fd()
+        .and_then(|fd| AsyncFd::with_interest(fd.as_raw_fd(), Interest::READABLE));
+    if let Ok(fd) = fd {
+        while !handle.is_finished() {
+            match fd.readable().await {
+                Ok(mut guard) if !handle.is_finished() => guard.clear_ready(),
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    }
+
+    // Without a registered descriptor, e.g. when out of file descriptors,
+    // have the thread notify the task directly.
+    if !handle.is_finished() {
+        handle.notify().notified().await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spawn;
+    use std::sync::mpsc;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn join_without_blocking() {
+        let (release, wait) = mpsc::channel::<()>();
+        let t = spawn(move || {
+            wait.recv().unwrap();
+            "done"
+        });
+
+        let result = runtime().block_on(async move {
+            let join = tokio::spawn(tokio_join(t));
+            // The single runtime thread is free to run other tasks while the thread runs.
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert!(!join.is_finished());
+            release.send(()).unwrap();
+            join.await.unwrap()
+        });
+        assert_eq!("done", result.unwrap());
+    }
+
+    #[test]
+    fn join_panicked() {
+        let t = spawn(|| panic!("boom"));
+        let result = runtime().block_on(tokio_join(t));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn timeout_gives_handle_back() {
+        let (release, wait) = mpsc::channel::<()>();
+        let t = spawn(move || wait.recv().unwrap());
+
+        let rt = runtime();
+        let t = rt
+            .block_on(tokio_join_timeout(t, Duration::from_millis(50)))
+            .unwrap_err();
+        release.send(()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let result = rt.block_on(tokio_join_until(t, deadline)).ok().unwrap();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn retries_reuse_descriptor() {
+        let (release, wait) = mpsc::channel::<()>();
+        let mut t = spawn(move || wait.recv().unwrap());
+
+        let rt = runtime();
+        for _ in 0..3 {
+            t = rt
+                .block_on(tokio_join_timeout(t, Duration::from_millis(10)))
+                .unwrap_err();
+        }
+        assert_eq!(1, t.completion().callbacks());
+
+        t.notify();
+        t.notify();
+        assert_eq!(2, t.completion().callbacks());
+
+        release.send(()).unwrap();
+        let result = rt
+            .block_on(tokio_join_timeout(t, Duration::from_secs(1)))
+            .ok()
+            .unwrap();
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,233 @@
+//! Diagnosing threads that don't finish in time.
+
+use std::fmt;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+use crate::{ThreadInfo, TryJoinHandle, TryJoinableHandle};
+
+/// What is known about a thread that didn't finish in time.
+#[derive(Clone, Debug)]
+pub struct HangReport {
+    /// The thread's name and kernel ID.
+    pub thread: ThreadInfo,
+    /// The thread's `pthread_t`, as an integer.
+    pub pthread: usize,
+    /// How long ago the thread was spawned.
+    pub running_for: Duration,
+    /// The contents of `/proc/self/task/<tid>/stat`, if requested and readable.
+    pub stat: Option<String>,
+    /// The kernel function the thread is blocked in, from `/proc/self/task/<tid>/wchan`,
+    /// if requested and readable.
+    pub wchan: Option<String>,
+}
+
+impl HangReport {
+    fn new<T>(handle: &TryJoinableHandle<T>, inspect_proc: bool) -> HangReport {
+        let (tid, pthread) = handle.native_ids();
+        let mut report = HangReport {
+            thread: handle.info(),
+            pthread,
+            running_for: handle.running_for(),
+            stat: None,
+            wchan: None,
+        };
+        if inspect_proc {
+            let read = |file| fs::read_to_string(format!("/proc/self/task/{}/{}", tid, file));
+            report.stat = read("stat").ok().map(|stat| stat.trim_end().to_string());
+            report.wchan = read("wchan")
+                .ok()
+                .filter(|wchan| !wchan.is_empty() && wchan != "0");
+        }
+        report
+    }
+
+    /// The scheduler state of the thread, e.g. `'S'` for sleeping or `'D'` for uninterruptible.
+    ///
+    /// Parsed from [`stat`](HangReport::stat).
+    pub fn state(&self) -> Option<char> {
+        // The thread name in parentheses can contain anything, including spaces and `)`.
+        let stat = self.stat.as_ref()?;
+        stat[stat.rfind(')')? + 1..].trim_start().chars().next()
+    }
+}
+
+impl fmt::Display for HangReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, pthread {:#x}, running for {:?}",
+            self.thread, self.pthread, self.running_for
+        )?;
+        if let Some(state) = self.state() {
+            write!(f, ", state {}", state)?;
+        }
+        if let Some(wchan) = &self.wchan {
+            write!(f, ", blocked in {}", wchan)?;
+        }
+        Ok(())
+    }
+}
+
+/// A thread that didn't finish in time, together with its handle.
+pub struct Hung<T> {
+    report: Box<HangReport>,
+    handle: TryJoinableHandle<T>,
+}
+
+impl<T> Hung<T> {
+    /// What is known about the thread.
+    pub fn report(&self) -> &HangReport {
+        &self.report
+    }
+
+    /// Get the handle back, e.g. to wait some more.
+    pub fn into_handle(self) -> TryJoinableHandle<T> {
+        self.handle
+    }
+}
+
+impl<T> fmt::Debug for Hung<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hung")
+            .field("report", &self.report)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Joins threads with a timeout and explains which thread hung, and where.
+///
+/// Only available on Linux.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use std::thread;
+/// use thread_tryjoin::Watchdog;
+///
+/// let t = thread_tryjoin::spawn(|| thread::sleep(Duration::from_millis(500)));
+///
+/// let watchdog = Watchdog::new(Duration::from_millis(50)).inspect_proc(true);
+/// match watchdog.join(t) {
+///     Ok(result) => assert!(result.is_ok()),
+///     Err(hung) => {
+///         eprintln!("{}", hung.report());
+///         assert!(hung.into_handle().join().is_ok());
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Watchdog {
+    timeout: Duration,
+    inspect_proc: bool,
+}
+
+impl Watchdog {
+    /// Create a watchdog giving threads `timeout` to finish.
+    pub fn new(timeout: Duration) -> Watchdog {
+        Watchdog {
+            timeout,
+            inspect_proc: false,
+        }
+    }
+
+    /// Whether to read `/proc/self/task/<tid>/stat` and `wchan` of hung threads.
+    pub fn inspect_proc(mut self, inspect_proc: bool) -> Watchdog {
+        self.inspect_proc = inspect_proc;
+        self
+    }
+
+    /// Join the thread, or report it as hung if it doesn't finish within the timeout.
+    pub fn join<T>(&self, handle: TryJoinableHandle<T>) -> Result<thread::Result<T>, Hung<T>> {
+        handle
+            .try_timed_join_into(self.timeout)
+            .map_err(|handle| Hung {
+                report: Box::new(self.inspect(&handle)),
+                handle,
+            })
+    }
+
+    /// Report on a thread right now, whether it hung or not.
+    pub fn inspect<T>(&self, handle: &TryJoinableHandle<T>) -> HangReport {
+        HangReport::new(handle, self.inspect_proc)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spawn;
+    use std::sync::mpsc;
+
+    #[test]
+    fn finished_in_time() {
+        let t = spawn(|| 42);
+        let result = Watchdog::new(Duration::from_secs(1)).join(t).unwrap();
+        assert_eq!(42, result.unwrap());
+    }
+
+    #[test]
+    fn reports_hung_thread() {
+        let (tx, rx) = mpsc::channel();
+        let (release, wait) = mpsc::channel::<()>();
+        let t = spawn(move || {
+            tx.send(unsafe { libc::gettid() }).unwrap();
+            wait.recv().unwrap();
+        });
+        let tid = rx.recv().unwrap();
+
+        let hung = Watchdog::new(Duration::from_millis(50))
+            .inspect_proc(true)
+            .join(t)
+            .unwrap_err();
+        let report = hung.report();
+        assert_eq!(Some(tid), report.thread.tid());
+        assert_ne!(0, report.pthread);
+        assert!(report.running_for >= Duration::from_millis(50));
+        assert!(report.stat.as_ref().unwrap().starts_with(&tid.to_string()));
+        assert_eq!(Some('S'), report.state());
+
+        let display = report.to_string();
+        assert!(
+            display.starts_with("thread '<unnamed>' (tid "),
+            "{}",
+            display
+        );
+        assert!(display.contains(", state S"), "{}", display);
+
+        release.send(()).unwrap();
+        assert!(hung.into_handle().join().is_ok());
+    }
+
+    #[test]
+    fn proc_is_only_read_on_request() {
+        let t = spawn(|| thread::sleep(Duration::from_millis(300)));
+
+        let report = Watchdog::new(Duration::ZERO).inspect(&t);
+        assert_eq!(None, report.stat);
+        assert_eq!(None, report.wchan);
+        assert_eq!(None, report.state());
+        assert!(t.join().is_ok());
+    }
+
+    #[test]
+    fn state_with_odd_thread_name() {
+        let report = HangReport {
+            thread: ThreadInfo {
+                name: Some("odd".to_string()),
+                tid: Some(1),
+            },
+            pthread: 1,
+            running_for: Duration::ZERO,
+            stat: Some("1 (a) b (c) D 0 1 1".to_string()),
+            wchan: Some("pipe_read".to_string()),
+        };
+        assert_eq!(Some('D'), report.state());
+        assert_eq!(
+            "thread 'odd' (tid 1), pthread 0x1, running for 0ns, state D, blocked in pipe_read",
+            report.to_string()
+        );
+    }
+}